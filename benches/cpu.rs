@@ -0,0 +1,47 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use nes_emu_rs::hardware::{CPU, LightweightState};
+use std::hint::black_box;
+
+/// Nested countdown loop, roughly 4k instructions per run:
+///
+/// ```asm
+///         LDY #$10
+/// outer:  LDX #$00
+/// inner:  INX
+///         BNE inner
+///         DEY
+///         BNE outer
+///         BRK
+/// ```
+const BUSY_LOOP: &[u8] = &[
+    0xa0, 0x10, 0xa2, 0x00, 0xe8, 0xd0, 0xfd, 0x88, 0xd0, 0xf8, 0x00,
+];
+
+fn run_busy_loop(c: &mut Criterion) {
+    let mut cpu = CPU::default();
+    cpu.load(BUSY_LOOP).unwrap();
+    c.bench_function("cpu busy loop", |b| {
+        b.iter(|| {
+            cpu.reset();
+            cpu.run().unwrap();
+            black_box(cpu.register_x)
+        })
+    });
+}
+
+fn lightweight_state(c: &mut Criterion) {
+    let mut cpu = CPU::default();
+    cpu.load(BUSY_LOOP).unwrap();
+    cpu.reset();
+    let mut state = LightweightState::default();
+
+    c.bench_function("lightweight state save", |b| {
+        b.iter(|| cpu.save_lightweight(black_box(&mut state)))
+    });
+    c.bench_function("lightweight state restore", |b| {
+        b.iter(|| cpu.load_lightweight(black_box(&state)))
+    });
+}
+
+criterion_group!(benches, run_busy_loop, lightweight_state);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use nes_emu_rs::{frame::Frame, scaler::Scaler};
+
+/// A full NES sized frame with some vertical and diagonal edges
+fn test_frame() -> Frame {
+    let mut frame = Frame::new(256, 240);
+    for y in 0..240 {
+        for x in 0..256 {
+            let value = if (x / 8 + y / 8) % 2 == 0 || x == y {
+                0xff
+            } else {
+                0
+            };
+            frame.set_pixel(x, y, (value, value / 2, 0));
+        }
+    }
+    frame
+}
+
+fn scalers(c: &mut Criterion) {
+    let src = test_frame();
+    for (name, scaler) in [("scale2x", Scaler::Scale2x), ("scale3x", Scaler::Scale3x)] {
+        let mut dst = scaler.output_frame(&src);
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                scaler.apply(&src, &mut dst);
+                dst.take_dirty_rows()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, scalers);
+criterion_main!(benches);
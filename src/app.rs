@@ -1,39 +1,168 @@
-use anyhow::Result;
+use std::fmt;
+
 use winit::{
     application::ApplicationHandler,
     event::WindowEvent,
-    event_loop::EventLoop,
-    keyboard::{KeyCode, PhysicalKey},
+    event_loop::{ActiveEventLoop, ControlFlow},
     window::Window,
 };
 
-use crate::hardware::{CPU, Gamepad};
+use crate::hardware::CPU;
+
+const TITLE: &str = "nes-emu-rs";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem that should be shown to the user rather than panicking,
+/// e.g. a ROM that failed to load
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+/// Tracks whether the window can be seen, so front-ends can stop emulating
+/// and presenting frames nobody is looking at
+#[derive(Debug, Clone)]
+pub struct IdleState {
+    /// Also pause when another window has focus, not only when minimized
+    pub pause_on_focus_loss: bool,
+    focused: bool,
+    minimized: bool,
+}
+
+impl Default for IdleState {
+    fn default() -> Self {
+        Self {
+            pause_on_focus_loss: false,
+            focused: true,
+            minimized: false,
+        }
+    }
+}
+
+impl IdleState {
+    pub fn new(pause_on_focus_loss: bool) -> Self {
+        Self {
+            pause_on_focus_loss,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    pub fn set_minimized(&mut self, minimized: bool) {
+        self.minimized = minimized;
+    }
+
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn should_pause(&self) -> bool {
+        self.minimized || (self.pause_on_focus_loss && !self.focused)
+    }
+}
 
 #[derive(Default)]
 pub struct App {
     window: Option<Window>,
+    #[allow(dead_code)]
     cpu: CPU,
-    focused: bool,
+    idle: IdleState,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl App {
     pub fn focused(&self) -> bool {
-        self.focused
+        self.idle.focused()
+    }
+
+    pub fn set_pause_on_focus_loss(&mut self, pause: bool) {
+        self.idle.pause_on_focus_loss = pause;
+    }
+
+    /// Whether emulation and presenting should be suspended
+    pub fn paused(&self) -> bool {
+        self.idle.should_pause()
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// Records a problem, logs it to stderr and shows the latest one in the
+    /// window title until there is a proper on-screen display
+    pub fn report(&mut self, severity: Severity, message: impl fmt::Display) {
+        let diagnostic = Diagnostic {
+            severity,
+            message: message.to_string(),
+        };
+        eprintln!("{diagnostic}");
+        self.diagnostics.push(diagnostic);
+        self.update_title();
+    }
+
+    fn update_title(&self) {
+        let Some(window) = &self.window else {
+            return;
+        };
+        match self.diagnostics.last() {
+            Some(diagnostic) => window.set_title(&format!("{TITLE} - {diagnostic}")),
+            None => window.set_title(TITLE),
+        }
     }
 }
 
 impl ApplicationHandler for App {
-    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        self.window = Some(
-            event_loop
-                .create_window(Window::default_attributes())
-                .unwrap(),
-        )
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        match event_loop.create_window(Window::default_attributes().with_title(TITLE)) {
+            Ok(window) => {
+                self.window = Some(window);
+                // Anything reported before the window existed
+                self.update_title();
+            }
+            Err(err) => {
+                // Nowhere to show anything, so bail out instead of panicking
+                self.report(Severity::Error, format!("failed to create window: {err}"));
+                event_loop.exit();
+            }
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        // Sleep until the next window event instead of spinning while paused
+        if self.paused() {
+            event_loop.set_control_flow(ControlFlow::Wait);
+        } else {
+            event_loop.set_control_flow(ControlFlow::Poll);
+        }
     }
 
     fn window_event(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
+        event_loop: &ActiveEventLoop,
         _window_id: winit::window::WindowId,
         event: winit::event::WindowEvent,
     ) {
@@ -42,7 +171,12 @@ impl ApplicationHandler for App {
                 println!("Close requested; stoppping");
                 event_loop.exit();
             }
-            WindowEvent::Focused(focused) => self.focused = focused,
+            WindowEvent::Focused(focused) => self.idle.set_focused(focused),
+            WindowEvent::Occluded(occluded) => self.idle.set_minimized(occluded),
+            // Some platforms report minimizing as a zero sized window
+            WindowEvent::Resized(size) => {
+                self.idle.set_minimized(size.width == 0 || size.height == 0)
+            }
             // WindowEvent::KeyboardInput { event, .. } => {
             //     if let PhysicalKey::Code(key_code) = event.physical_key {
             //         match key_code {
@@ -67,3 +201,23 @@ impl ApplicationHandler for App {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_idle_state() {
+        let mut idle = IdleState::default();
+        idle.set_focused(false);
+        assert!(!idle.should_pause());
+
+        idle.pause_on_focus_loss = true;
+        assert!(idle.should_pause());
+        idle.set_focused(true);
+        assert!(!idle.should_pause());
+
+        idle.set_minimized(true);
+        assert!(idle.should_pause());
+    }
+}
@@ -0,0 +1,305 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hardware::{Cheat, CheatId, Cheats},
+    paths::{DataDirs, DataKind},
+};
+
+/// Letters of the Game Genie alphabet, in order of the nibble they encode
+const GAME_GENIE_LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+/// A named cheat as stored in a cheat file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheatEntry {
+    pub name: String,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    /// The Game Genie code the cheat was imported from, for display
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(flatten)]
+    pub cheat: Cheat,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// The cheats for one game, stored as TOML in the cheats directory:
+///
+/// ```toml
+/// [[cheats]]
+/// name = "Infinite lives"
+/// code = "SXIOPO"
+/// type = "replace"
+/// address = 0x91D9
+/// value = 0xAD
+///
+/// [[cheats]]
+/// name = "Full health"
+/// type = "freeze"
+/// address = 0x0075
+/// value = 0x09
+/// lock = true
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheatFile {
+    #[serde(default)]
+    pub cheats: Vec<CheatEntry>,
+}
+
+impl CheatFile {
+    /// Where the cheats for `game` (e.g. the ROM's file stem) are kept
+    pub fn path(dirs: &DataDirs, game: &str) -> PathBuf {
+        dirs.dir(DataKind::Cheats).join(format!("{game}.toml"))
+    }
+
+    /// Loads a cheat file, treating a missing one as empty
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let source =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        toml::from_str(&source).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Adds every cheat to `cheats`, keeping disabled ones around switched off
+    pub fn apply(&self, cheats: &mut Cheats) -> Vec<CheatId> {
+        self.cheats
+            .iter()
+            .map(|entry| {
+                let id = cheats.add(entry.cheat);
+                cheats.set_enabled(id, entry.enabled);
+                id
+            })
+            .collect()
+    }
+
+    /// Imports an FCEUX `.cht` file, where each line is
+    /// `[S][C][:]address:value[:compare]:name`. `S` marks a replace-on-read
+    /// cheat rather than a RAM write every frame, `C` that a compare value
+    /// follows and a leading `:` that the cheat is disabled.
+    pub fn import_fceux(source: &str) -> Result<Self> {
+        let mut cheats = vec![];
+        for (idx, line) in source.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = parse_fceux_line(line).with_context(|| format!("line {}", idx + 1))?;
+            cheats.push(entry);
+        }
+        Ok(Self { cheats })
+    }
+
+    /// Imports a Game Genie code list with one `CODE name` line per cheat.
+    /// Codes can be combined with `+` and separated from the name by `-` or
+    /// `:`; lines starting with `#` are comments.
+    pub fn import_game_genie(source: &str) -> Result<Self> {
+        let mut cheats = vec![];
+        for (idx, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (codes, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let name = name.trim().trim_start_matches(['-', ':']).trim();
+            for code in codes.split('+') {
+                let cheat = decode_game_genie(code)
+                    .with_context(|| format!("line {}: invalid code {code:?}", idx + 1))?;
+                cheats.push(CheatEntry {
+                    name: name.to_string(),
+                    enabled: true,
+                    code: Some(code.to_ascii_uppercase()),
+                    cheat,
+                });
+            }
+        }
+        Ok(Self { cheats })
+    }
+}
+
+fn parse_fceux_line(line: &str) -> Result<CheatEntry> {
+    let mut rest = line;
+    let replace = rest.starts_with('S');
+    if replace {
+        rest = &rest[1..];
+    }
+    let has_compare = rest.starts_with('C');
+    if has_compare {
+        rest = &rest[1..];
+    }
+    let enabled = !rest.starts_with(':');
+    if !enabled {
+        rest = &rest[1..];
+    }
+
+    let fields = if has_compare { 4 } else { 3 };
+    let parts: Vec<&str> = rest.splitn(fields, ':').collect();
+    if parts.len() != fields {
+        bail!(
+            "expected address:value{}:name",
+            if has_compare { ":compare" } else { "" }
+        );
+    }
+    let hex = |field: &str| {
+        u16::from_str_radix(field, 16).with_context(|| format!("{field:?} is not hex"))
+    };
+    let address = hex(parts[0])?;
+    let value = hex(parts[1])? as u8;
+    let compare = if has_compare {
+        Some(hex(parts[2])? as u8)
+    } else {
+        None
+    };
+
+    let cheat = if replace {
+        Cheat::Replace {
+            address,
+            value,
+            compare,
+        }
+    } else {
+        Cheat::Freeze {
+            address,
+            value,
+            lock: false,
+        }
+    };
+    Ok(CheatEntry {
+        name: parts[fields - 1].to_string(),
+        enabled,
+        code: None,
+        cheat,
+    })
+}
+
+/// Decodes a 6 or 8 letter Game Genie code into the ROM patch it applies,
+/// see https://tuxnes.sourceforge.net/gamegenie.html
+pub fn decode_game_genie(code: &str) -> Result<Cheat> {
+    let n = code
+        .bytes()
+        .map(|letter| {
+            GAME_GENIE_LETTERS
+                .iter()
+                .position(|known| *known == letter.to_ascii_uppercase())
+                .map(|nibble| nibble as u16)
+                .context("not a Game Genie letter")
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if n.len() != 6 && n.len() != 8 {
+        bail!("Game Genie codes have 6 or 8 letters");
+    }
+
+    let address = 0x8000
+        + (((n[3] & 7) << 12)
+            | ((n[5] & 7) << 8)
+            | ((n[4] & 8) << 8)
+            | ((n[2] & 7) << 4)
+            | ((n[1] & 8) << 4)
+            | (n[4] & 7)
+            | (n[3] & 8));
+    let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+
+    Ok(if n.len() == 6 {
+        Cheat::Replace {
+            address,
+            value: (value | (n[5] & 8)) as u8,
+            compare: None,
+        }
+    } else {
+        Cheat::Replace {
+            address,
+            value: (value | (n[7] & 8)) as u8,
+            compare: Some((((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8)) as u8),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_game_genie() {
+        assert_eq!(
+            decode_game_genie("SXIOPO").unwrap(),
+            Cheat::Replace {
+                address: 0x91D9,
+                value: 0xAD,
+                compare: None,
+            }
+        );
+        assert!(matches!(
+            decode_game_genie("yeuzugaa").unwrap(),
+            Cheat::Replace {
+                compare: Some(_),
+                ..
+            }
+        ));
+        assert!(decode_game_genie("SXIOP").is_err());
+        assert!(decode_game_genie("SXIOPB").is_err());
+    }
+
+    #[test]
+    fn test_import_fceux() {
+        let file =
+            CheatFile::import_fceux("0075:09:Lives\n:SC00A0:01:02:Off\nSC:0100:FF:00:Replace\n");
+        // The S and C flags come before the disabled marker
+        assert!(file.is_err());
+
+        let file =
+            CheatFile::import_fceux("0075:09:Lives\nSC:0100:FF:00:Disabled: with colon\n").unwrap();
+        assert_eq!(
+            file.cheats[0],
+            CheatEntry {
+                name: "Lives".into(),
+                enabled: true,
+                code: None,
+                cheat: Cheat::Freeze {
+                    address: 0x75,
+                    value: 0x09,
+                    lock: false
+                },
+            }
+        );
+        assert!(!file.cheats[1].enabled);
+        assert_eq!(file.cheats[1].name, "Disabled: with colon");
+        assert_eq!(
+            file.cheats[1].cheat,
+            Cheat::Replace {
+                address: 0x100,
+                value: 0xFF,
+                compare: Some(0x00),
+            }
+        );
+    }
+
+    #[test]
+    fn test_game_genie_list_round_trips_through_toml() {
+        let file =
+            CheatFile::import_game_genie("# Super Mario Bros.\nSXIOPO - Infinite lives\n").unwrap();
+        assert_eq!(file.cheats[0].name, "Infinite lives");
+        assert_eq!(file.cheats[0].code.as_deref(), Some("SXIOPO"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = CheatFile::path(&DataDirs::rooted_at(dir.path()), "smb");
+        file.save(&path).unwrap();
+        assert_eq!(CheatFile::load(&path).unwrap(), file);
+
+        let mut cheats = Cheats::default();
+        let ids = file.apply(&mut cheats);
+        assert!(cheats.is_enabled(ids[0]));
+    }
+}
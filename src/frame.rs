@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+/// An RGB24 frame buffer that remembers which rows changed since the
+/// last upload so front-ends only need to copy those to the GPU
+pub struct Frame {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+    dirty: Option<Range<usize>>,
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; width * height * 3],
+            // Everything needs to be uploaded the first time
+            dirty: Some(0..height),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Number of bytes in a single row
+    pub fn pitch(&self) -> usize {
+        self.width * 3
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+        let base = (y * self.width + x) * 3;
+        let pixel = [rgb.0, rgb.1, rgb.2];
+        if self.data[base..base + 3] == pixel {
+            return;
+        }
+        self.data[base..base + 3].copy_from_slice(&pixel);
+        self.dirty = Some(match self.dirty.take() {
+            Some(rows) => rows.start.min(y)..rows.end.max(y + 1),
+            None => y..y + 1,
+        });
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// Returns the range of rows changed since the last call and clears it
+    pub fn take_dirty_rows(&mut self) -> Option<Range<usize>> {
+        self.dirty.take()
+    }
+
+    /// The bytes backing the given rows, suitable for a partial texture upload
+    pub fn rows(&self, rows: Range<usize>) -> &[u8] {
+        &self.data[rows.start * self.pitch()..rows.end * self.pitch()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new_frame_is_fully_dirty() {
+        let mut frame = Frame::new(4, 3);
+        assert_eq!(frame.take_dirty_rows(), Some(0..3));
+        assert_eq!(frame.take_dirty_rows(), None);
+    }
+
+    #[test]
+    fn test_dirty_rows_span_changes() {
+        let mut frame = Frame::new(4, 8);
+        frame.take_dirty_rows();
+
+        frame.set_pixel(1, 5, (1, 2, 3));
+        frame.set_pixel(0, 2, (4, 5, 6));
+        // Writing the same colour again doesn't widen the range
+        frame.set_pixel(3, 7, (0, 0, 0));
+
+        assert_eq!(frame.take_dirty_rows(), Some(2..6));
+        assert_eq!(frame.rows(5..6)[3..6], [1, 2, 3]);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{fmt, time::Duration};
+
+/// Number of frames kept in the rolling window
+const WINDOW: usize = 120;
+/// Width of a single histogram bucket
+const BUCKET_WIDTH: Duration = Duration::from_millis(4);
+/// Number of histogram buckets, the last one collects everything above it
+pub const BUCKETS: usize = 8;
+
+/// Time spent in each stage of producing a single frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTiming {
+    pub emulation: Duration,
+    pub render: Duration,
+    pub present: Duration,
+}
+
+impl FrameTiming {
+    pub fn total(&self) -> Duration {
+        self.emulation + self.render + self.present
+    }
+}
+
+/// Rolling statistics over the last [`WINDOW`] frames
+pub struct FrameStats {
+    samples: [FrameTiming; WINDOW],
+    next: usize,
+    len: usize,
+    frames: u64,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            samples: [FrameTiming::default(); WINDOW],
+            next: 0,
+            len: 0,
+            frames: 0,
+        }
+    }
+}
+
+impl FrameStats {
+    pub fn push(&mut self, timing: FrameTiming) {
+        self.samples[self.next] = timing;
+        self.next = (self.next + 1) % WINDOW;
+        self.len = (self.len + 1).min(WINDOW);
+        self.frames += 1;
+    }
+
+    /// Total number of frames recorded, including ones that fell out of the window
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    fn window(&self) -> &[FrameTiming] {
+        &self.samples[..self.len]
+    }
+
+    pub fn average(&self) -> FrameTiming {
+        if self.len == 0 {
+            return FrameTiming::default();
+        }
+        let len = self.len as u32;
+        let sum = self
+            .window()
+            .iter()
+            .fold(FrameTiming::default(), |acc, timing| FrameTiming {
+                emulation: acc.emulation + timing.emulation,
+                render: acc.render + timing.render,
+                present: acc.present + timing.present,
+            });
+
+        FrameTiming {
+            emulation: sum.emulation / len,
+            render: sum.render / len,
+            present: sum.present / len,
+        }
+    }
+
+    /// The frame with the longest total time in the window
+    pub fn worst(&self) -> FrameTiming {
+        self.window()
+            .iter()
+            .copied()
+            .max_by_key(FrameTiming::total)
+            .unwrap_or_default()
+    }
+
+    /// Counts of total frame times in [`BUCKET_WIDTH`] wide buckets
+    pub fn histogram(&self) -> [usize; BUCKETS] {
+        let mut buckets = [0; BUCKETS];
+        for timing in self.window() {
+            let idx = (timing.total().as_micros() / BUCKET_WIDTH.as_micros()) as usize;
+            buckets[idx.min(BUCKETS - 1)] += 1;
+        }
+        buckets
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let average = self.average();
+        write!(
+            f,
+            "emu {:.1}ms render {:.1}ms present {:.1}ms | worst {:.1}ms |",
+            ms(average.emulation),
+            ms(average.render),
+            ms(average.present),
+            ms(self.worst().total()),
+        )?;
+
+        // Scale the histogram so the fullest bucket is drawn as a full block
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let histogram = self.histogram();
+        let max = histogram.iter().copied().max().unwrap_or(0).max(1);
+        for count in histogram {
+            if count == 0 {
+                write!(f, " ")?;
+            } else {
+                write!(f, "{}", BARS[(count * (BARS.len() - 1)) / max])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(emulation: u64, render: u64, present: u64) -> FrameTiming {
+        FrameTiming {
+            emulation: Duration::from_millis(emulation),
+            render: Duration::from_millis(render),
+            present: Duration::from_millis(present),
+        }
+    }
+
+    #[test]
+    fn test_average_and_worst() {
+        let mut stats = FrameStats::default();
+        stats.push(frame(2, 1, 1));
+        stats.push(frame(4, 1, 9));
+
+        assert_eq!(stats.average(), frame(3, 1, 5));
+        assert_eq!(stats.worst(), frame(4, 1, 9));
+    }
+
+    #[test]
+    fn test_window_rolls_over() {
+        let mut stats = FrameStats::default();
+        stats.push(frame(100, 0, 0));
+        for _ in 0..WINDOW {
+            stats.push(frame(1, 0, 0));
+        }
+
+        assert_eq!(stats.frames(), WINDOW as u64 + 1);
+        assert_eq!(stats.worst(), frame(1, 0, 0));
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut stats = FrameStats::default();
+        stats.push(frame(1, 0, 0));
+        stats.push(frame(5, 0, 0));
+        stats.push(frame(500, 0, 0));
+
+        assert_eq!(stats.histogram(), [1, 1, 0, 0, 0, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,883 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::Region;
+
+/// NTSC CPU clock, which also drives the APU
+pub const CPU_CLOCK_HZ: u32 = 1_789_773;
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Samples kept for the front-end, about 90ms at 44.1kHz
+const SAMPLE_BUFFER_SIZE: usize = 4096;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+const NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+/// In CPU cycles
+const DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+/// Frame sequencer steps in CPU cycles
+#[derive(Debug, Clone, Copy)]
+struct FrameSteps {
+    quarter_frame_1: u32,
+    half_frame_1: u32,
+    quarter_frame_3: u32,
+    four_step_end: u32,
+    five_step_end: u32,
+}
+
+impl FrameSteps {
+    /// Dendy keeps NTSC's table, so its sequencer runs slightly slower
+    fn for_region(region: Region) -> Self {
+        match region {
+            Region::Ntsc | Region::Dendy => FrameSteps {
+                quarter_frame_1: 7457,
+                half_frame_1: 14913,
+                quarter_frame_3: 22371,
+                four_step_end: 29829,
+                five_step_end: 37281,
+            },
+            Region::Pal => FrameSteps {
+                quarter_frame_1: 8313,
+                half_frame_1: 16627,
+                quarter_frame_3: 24939,
+                four_step_end: 33253,
+                five_step_end: 41565,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[index as usize];
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    /// Constant volume, or the divider period
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8) {
+        self.looping = data & 0x20 != 0;
+        self.constant = data & 0x10 != 0;
+        self.volume = data & 0x0F;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct Pulse {
+    /// Pulse 1 negates its sweep with ones' complement instead of two's
+    ones_complement: bool,
+    duty: u8,
+    duty_position: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = data >> 6;
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period = (data >> 4) & 0x07;
+                self.sweep_negate = data & 0x08 != 0;
+                self.sweep_shift = data & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0x07) << 8);
+                self.length.load(data >> 3);
+                self.duty_position = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            self.timer_period
+                .saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x07FF
+    }
+
+    /// Every other CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.duty_position = (self.duty_position + 1) & 7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.length.active()
+            || self.muted()
+            || DUTY_TABLE[self.duty as usize][self.duty_position as usize] == 0
+        {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct Triangle {
+    timer_period: u16,
+    timer: u16,
+    step: u8,
+    length: LengthCounter,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+}
+
+impl Triangle {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                // The same bit halts the length counter and controls the
+                // linear counter
+                self.length.halt = data & 0x80 != 0;
+                self.linear_reload_value = data & 0x7F;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0x07) << 8);
+                self.length.load(data >> 3);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    /// Every CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.active() {
+                self.step = (self.step + 1) & 31;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.length.halt {
+            self.linear_reload = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        TRIANGLE_TABLE[self.step as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Noise {
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            short_mode: false,
+            timer_period: NOISE_PERIODS[0],
+            timer: 0,
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                self.timer_period = NOISE_PERIODS[(data & 0x0F) as usize];
+            }
+            _ => {
+                self.length.load(data >> 3);
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Every other CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.shift & 1 == 1 || !self.length.active() {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+/// The delta modulation channel, which plays 1-bit delta samples straight
+/// out of CPU memory
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    irq: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            irq_enabled: false,
+            looping: false,
+            timer_period: DMC_RATES[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            irq: false,
+        }
+    }
+}
+
+impl Dmc {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.looping = data & 0x40 != 0;
+                self.timer_period = DMC_RATES[(data & 0x0F) as usize];
+            }
+            1 => self.level = data & 0x7F,
+            2 => self.sample_address = 0xC000 | (data as u16) << 6,
+            _ => self.sample_length = (data as u16) << 4 | 1,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    /// Address the memory reader wants the next sample byte from
+    fn pending_read(&self) -> Option<u16> {
+        (self.buffer.is_none() && self.bytes_remaining > 0).then_some(self.current_address)
+    }
+
+    fn fill(&mut self, data: u8) {
+        self.buffer = Some(data);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Every CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift & 1 == 1 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(data) => {
+                    self.silence = false;
+                    self.shift = data;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+}
+
+/// Fixed size queue of mixed samples between the emulator and the audio
+/// device. When the front-end falls behind the oldest samples are dropped.
+#[derive(Debug)]
+pub struct SampleBuffer {
+    data: Box<[f32]>,
+    read: usize,
+    len: usize,
+}
+
+impl Default for SampleBuffer {
+    fn default() -> Self {
+        Self::with_capacity(SAMPLE_BUFFER_SIZE)
+    }
+}
+
+impl SampleBuffer {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data: vec![0.0; capacity].into_boxed_slice(),
+            read: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        let capacity = self.data.len();
+        let write = (self.read + self.len) % capacity;
+        self.data[write] = sample;
+        if self.len == capacity {
+            self.read = (self.read + 1) % capacity;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    /// Moves as many samples as fit into `out`, oldest first. Returns how
+    /// many were written.
+    pub fn pull(&mut self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.len);
+        for sample in out.iter_mut().take(count) {
+            *sample = self.data[self.read];
+            self.read = (self.read + 1) % self.data.len();
+        }
+        self.len -= count;
+        count
+    }
+}
+
+/// The audio processing unit: two pulse channels, triangle, noise and DMC,
+/// clocked once per CPU cycle and mixed down to [`SAMPLE_RATE`] samples
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    /// CPU cycles into the current frame sequence
+    frame_cycle: u32,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    odd_cycle: bool,
+    /// Sets the CPU clock `tick` is called at and the frame sequencer's
+    /// steps
+    region: Region,
+    sample_rate: u32,
+    /// Fractional progress towards the next output sample
+    sample_clock: u32,
+    /// Level of the cartridge's expansion audio, see
+    /// [`Apu::set_expansion_output`]
+    expansion: f32,
+    /// High-pass filter state, removes the DC offset of the mixer
+    filter_in: f32,
+    filter_out: f32,
+    #[serde(skip)]
+    pub samples: SampleBuffer,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new(SAMPLE_RATE)
+    }
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            pulse1: Pulse {
+                ones_complement: true,
+                ..Default::default()
+            },
+            pulse2: Pulse::default(),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_cycle: 0,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            odd_cycle: false,
+            region: Region::Ntsc,
+            sample_rate,
+            sample_clock: 0,
+            expansion: 0.0,
+            filter_in: 0.0,
+            filter_out: 0.0,
+            samples: SampleBuffer::default(),
+        }
+    }
+
+    /// Matches the console's timing, so output samples keep coming at the
+    /// sample rate and the frame sequencer at its usual rate on other
+    /// regions
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Takes over the channels and frame counter of an APU loaded from a
+    /// savestate, keeping this one's output settings and queued samples
+    pub(crate) fn restore(&mut self, saved: Apu) {
+        *self = Apu {
+            region: self.region,
+            sample_rate: self.sample_rate,
+            samples: std::mem::take(&mut self.samples),
+            ..saved
+        };
+    }
+
+    /// Sets what the cartridge's sound chip is outputting, which the mixer
+    /// adds to the console's own channels
+    pub fn set_expansion_output(&mut self, level: f32) {
+        self.expansion = level;
+    }
+
+    /// A CPU write to `$4000..=$4013`, `$4015` or `$4017`
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr & 3, data),
+            0x4004..=0x4007 => self.pulse2.write(addr & 3, data),
+            0x4008..=0x400B => self.triangle.write(addr & 3, data),
+            0x400C..=0x400F => self.noise.write(addr & 3, data),
+            0x4010..=0x4013 => self.dmc.write(addr & 3, data),
+            0x4015 => {
+                self.pulse1.length.set_enabled(data & 0x01 != 0);
+                self.pulse2.length.set_enabled(data & 0x02 != 0);
+                self.triangle.length.set_enabled(data & 0x04 != 0);
+                self.noise.length.set_enabled(data & 0x08 != 0);
+                self.dmc.set_enabled(data & 0x10 != 0);
+            }
+            0x4017 => {
+                self.five_step = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A CPU read of `$4015`, which acknowledges the frame IRQ
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    /// `$4015` without acknowledging the frame IRQ
+    pub fn peek_status(&self) -> u8 {
+        (self.pulse1.length.active() as u8)
+            | (self.pulse2.length.active() as u8) << 1
+            | (self.triangle.length.active() as u8) << 2
+            | (self.noise.length.active() as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq as u8) << 7
+    }
+
+    /// Whether the frame counter or DMC is asserting the IRQ line
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    /// Address the DMC needs a sample byte from, see [`Apu::dmc_fill`]
+    pub fn dmc_pending_read(&self) -> Option<u16> {
+        self.dmc.pending_read()
+    }
+
+    pub fn dmc_fill(&mut self, data: u8) {
+        self.dmc.fill(data);
+    }
+
+    /// Advances one CPU cycle
+    pub fn tick(&mut self) {
+        self.triangle.clock_timer();
+        self.dmc.clock_timer();
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+            self.noise.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.clock_frame_counter();
+
+        self.sample_clock += self.sample_rate;
+        let cpu_clock = self.region.cpu_clock_hz();
+        if self.sample_clock >= cpu_clock {
+            self.sample_clock -= cpu_clock;
+            let sample = self.high_pass(self.mix());
+            self.samples.push(sample);
+        }
+    }
+
+    fn clock_frame_counter(&mut self) {
+        let steps = FrameSteps::for_region(self.region);
+        self.frame_cycle += 1;
+        match self.frame_cycle {
+            cycle if cycle == steps.quarter_frame_1 || cycle == steps.quarter_frame_3 => {
+                self.clock_quarter_frame()
+            }
+            cycle if cycle == steps.half_frame_1 => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            cycle if cycle == steps.four_step_end && !self.five_step => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if !self.irq_inhibit {
+                    self.frame_irq = true;
+                }
+                self.frame_cycle = 0;
+            }
+            cycle if cycle == steps.five_step_end => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                self.frame_cycle = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    /// The console's nonlinear DAC, in `0.0..=1.0`
+    fn mix(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.level as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out + self.expansion
+    }
+
+    /// First order high-pass at about 90Hz, like the console's output stage
+    fn high_pass(&mut self, sample: f32) -> f32 {
+        const ALPHA: f32 = 0.987;
+        let out = ALPHA * (self.filter_out + sample - self.filter_in);
+        self.filter_in = sample;
+        self.filter_out = out;
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(apu: &mut Apu, cycles: u32) {
+        for _ in 0..cycles {
+            apu.tick();
+        }
+    }
+
+    #[test]
+    fn test_length_counter_and_status() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0x01);
+        // Halt off, constant volume 15, length index 1 = 254
+        apu.write_register(0x4000, 0x1F);
+        apu.write_register(0x4003, 0x08);
+        assert_eq!(apu.peek_status() & 0x01, 0x01);
+
+        apu.write_register(0x4015, 0x00);
+        assert_eq!(apu.peek_status() & 0x01, 0x00);
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let steps = FrameSteps::for_region(Region::Ntsc);
+        let mut apu = Apu::default();
+        run(&mut apu, steps.four_step_end);
+        assert!(apu.irq());
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq());
+
+        // 5-step mode never raises it
+        apu.write_register(0x4017, 0x80);
+        run(&mut apu, steps.five_step_end * 2);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_pal_frame_sequencer() {
+        let ntsc = FrameSteps::for_region(Region::Ntsc);
+        let pal = FrameSteps::for_region(Region::Pal);
+        let mut apu = Apu::default();
+        apu.set_region(Region::Pal);
+        apu.write_register(0x4015, 0x01);
+        // Halt off, length index 1 = 254
+        apu.write_register(0x4000, 0x1F);
+        apu.write_register(0x4003, 0x08);
+
+        // Nothing happens on NTSC's steps
+        run(&mut apu, ntsc.half_frame_1);
+        assert_eq!(apu.pulse1.length.counter, 254);
+        run(&mut apu, pal.half_frame_1 - ntsc.half_frame_1);
+        assert_eq!(apu.pulse1.length.counter, 253);
+
+        run(&mut apu, ntsc.four_step_end - pal.half_frame_1);
+        assert!(!apu.irq());
+        run(&mut apu, pal.four_step_end - ntsc.four_step_end);
+        assert_eq!(apu.pulse1.length.counter, 252);
+        assert!(apu.irq());
+    }
+
+    #[test]
+    fn test_pulse_produces_samples() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4000, 0xBF);
+        // ~440Hz
+        apu.write_register(0x4002, 0xFD);
+        apu.write_register(0x4003, 0x08);
+        run(&mut apu, CPU_CLOCK_HZ / 60);
+
+        let mut out = [0.0; 1024];
+        let count = apu.samples.pull(&mut out);
+        assert_eq!(count, 734);
+        assert!(out[..count].iter().any(|sample| *sample > 0.05));
+        assert!(out[..count].iter().any(|sample| *sample < -0.05));
+    }
+
+    #[test]
+    fn test_dmc_reads_samples() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4012, 0x01);
+        apu.write_register(0x4013, 0x00);
+        apu.write_register(0x4015, 0x10);
+        assert_eq!(apu.dmc_pending_read(), Some(0xC040));
+        apu.dmc_fill(0xFF);
+        assert_eq!(apu.dmc_pending_read(), None);
+        assert_eq!(apu.peek_status() & 0x10, 0);
+    }
+
+    #[test]
+    fn test_sample_buffer_drops_oldest() {
+        let mut buffer = SampleBuffer::with_capacity(3);
+        for sample in 0..5 {
+            buffer.push(sample as f32);
+        }
+        let mut out = [0.0; 4];
+        assert_eq!(buffer.pull(&mut out), 3);
+        assert_eq!(out[..3], [2.0, 3.0, 4.0]);
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,458 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::hardware::{
+    Apu, Cartridge, Cheats, ControllerPort, EmuError, FrameCounter, Mirroring, Nrom, Ppu, Region,
+    Rom, StandardController, create_mapper,
+};
+
+const RAM: u16 = 0x0000;
+const RAM_MIRRORS_END: u16 = 0x1FFF;
+const PPU_REGISTERS: u16 = 0x2000;
+const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const APU_REGISTERS: u16 = 0x4000;
+const APU_REGISTERS_END: u16 = 0x4013;
+const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
+const JOYPAD1: u16 = 0x4016;
+const JOYPAD2: u16 = 0x4017;
+const CARTRIDGE: u16 = 0x4020;
+const CARTRIDGE_END: u16 = 0xFFFF;
+
+pub(crate) const RAM_SIZE: usize = 0x0800;
+
+/// Everything the CPU can address, laid out like the NES memory map:
+///
+/// ```text
+/// 0x0000..0x2000  2KB internal RAM, mirrored every 0x0800
+/// 0x2000..0x4000  8 PPU registers, mirrored every 8 bytes
+/// 0x4000..0x4014  APU channel registers
+/// 0x4014          OAM DMA
+/// 0x4015          APU status
+/// 0x4016/0x4017   controller ports, writes to 0x4016 strobe both and
+///                 writes to 0x4017 go to the APU frame counter
+/// 0x4020..        the cartridge's mapper, PRG ROM from 0x8000
+/// ```
+pub struct Bus {
+    cpu_vram: [u8; RAM_SIZE],
+    pub ppu: Ppu,
+    pub apu: Apu,
+    cartridge: Cartridge,
+    pub cheats: Cheats,
+    /// Devices plugged into the two controller ports, standard controllers
+    /// by default
+    pub controllers: [Box<dyn ControllerPort>; 2],
+    frame_counter: FrameCounter,
+    region: Region,
+    /// Leftover fraction of a PPU dot on regions without a whole number of
+    /// dots per CPU cycle
+    dot_remainder: usize,
+    /// The cartridge keeps its PRG RAM when switched off
+    battery: bool,
+    /// An OAM DMA ran that the CPU hasn't been halted for yet
+    oam_dma: bool,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        let cartridge: Cartridge = Rc::new(RefCell::new(Nrom::default()));
+        Self {
+            cpu_vram: [0; RAM_SIZE],
+            ppu: Ppu::new(cartridge.clone()),
+            apu: Apu::default(),
+            cartridge,
+            cheats: Cheats::default(),
+            controllers: [
+                Box::new(StandardController::default()),
+                Box::new(StandardController::default()),
+            ],
+            frame_counter: FrameCounter::default(),
+            region: Region::default(),
+            dot_remainder: 0,
+            battery: false,
+            oam_dma: false,
+        }
+    }
+}
+
+impl Bus {
+    /// Swaps in an NROM cartridge with the given PRG ROM and CHR RAM,
+    /// keeping the PPU state. 16KB ROMs are mirrored into both halves of the
+    /// cartridge space.
+    pub fn load_prg_rom(&mut self, prg_rom: Vec<u8>) -> Result<(), EmuError> {
+        let nrom = Nrom::new(prg_rom, vec![], Mirroring::Horizontal)?;
+        self.insert_cartridge(Rc::new(RefCell::new(nrom)));
+        Ok(())
+    }
+
+    /// Creates the mapper the cartridge needs and connects it to a fresh PPU
+    pub fn load_rom(&mut self, rom: &Rom) -> Result<(), EmuError> {
+        self.cartridge = create_mapper(rom)?;
+        self.ppu = Ppu::new(self.cartridge.clone());
+        self.ppu.set_region(self.region);
+        self.battery = rom.battery;
+        Ok(())
+    }
+
+    /// A copy of the cartridge's battery backed RAM, for writing to a
+    /// `.sav` file. `None` if the cartridge has no battery.
+    pub fn battery_ram(&self) -> Option<Vec<u8>> {
+        if !self.battery {
+            return None;
+        }
+        self.cartridge.borrow().prg_ram().map(<[u8]>::to_vec)
+    }
+
+    /// Restores battery RAM saved with [`Bus::battery_ram`], which must be
+    /// from a cartridge with the same amount of RAM
+    pub fn load_battery_ram(&mut self, ram: &[u8]) -> Result<(), EmuError> {
+        if !self.battery {
+            return Err(EmuError::InvalidBatterySave(
+                "the cartridge has no battery".to_string(),
+            ));
+        }
+        let mut cartridge = self.cartridge.borrow_mut();
+        let Some(prg_ram) = cartridge.prg_ram_mut() else {
+            return Err(EmuError::InvalidBatterySave(
+                "the cartridge has no PRG RAM".to_string(),
+            ));
+        };
+        if prg_ram.len() != ram.len() {
+            return Err(EmuError::InvalidBatterySave(format!(
+                "expected {} bytes, got {}",
+                prg_ram.len(),
+                ram.len()
+            )));
+        }
+        prg_ram.copy_from_slice(ram);
+        Ok(())
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Switches the console's timing. Takes effect from the next cycle, so
+    /// this is best done before the game starts.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.dot_remainder = 0;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+        self.frame_counter.set_region(region);
+    }
+
+    /// Connects `cartridge` to both the CPU bus and the PPU. Without an
+    /// iNES header there's no battery flag, so its RAM isn't persisted.
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.ppu.set_cartridge(cartridge.clone());
+        self.cartridge = cartridge;
+        self.battery = false;
+    }
+
+    pub(crate) fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    /// Whether anything answers at `addr`, i.e. it isn't open bus. All of
+    /// the cartridge space counts, games also run code from PRG RAM.
+    pub fn is_mapped(&self, addr: u16) -> bool {
+        matches!(
+            addr,
+            RAM..=PPU_REGISTERS_MIRRORS_END | APU_REGISTERS..=JOYPAD2 | CARTRIDGE..=CARTRIDGE_END
+        )
+    }
+
+    /// Advances the rest of the system by `cycles` CPU cycles. Returns
+    /// whether the PPU finished a frame.
+    pub fn tick(&mut self, cycles: u16) -> bool {
+        for _ in 0..cycles {
+            let expansion = {
+                let mut cartridge = self.cartridge.borrow_mut();
+                cartridge.tick();
+                cartridge.audio_output()
+            };
+            self.apu.set_expansion_output(expansion);
+            self.apu.tick();
+            if let Some(addr) = self.apu.dmc_pending_read() {
+                let data = self.read(addr);
+                self.apu.dmc_fill(data);
+            }
+        }
+        if let Some(rtc) = self.cartridge.borrow_mut().rtc_mut() {
+            rtc.tick(cycles);
+        }
+        let (numerator, denominator) = self.region.dots_per_cycle();
+        let dots = cycles as usize * numerator + self.dot_remainder;
+        self.dot_remainder = dots % denominator;
+        let frame_done = self.ppu.tick(dots / denominator);
+        if frame_done {
+            self.frame_counter.end_frame();
+        }
+        frame_done
+    }
+
+    pub fn frame_counter(&self) -> &FrameCounter {
+        &self.frame_counter
+    }
+
+    /// Returns and clears a pending NMI request
+    pub fn poll_nmi(&mut self) -> bool {
+        self.ppu.take_nmi()
+    }
+
+    /// Returns and clears whether a `$4014` write copied a page into OAM.
+    /// The CPU is halted for 513 or 514 cycles while that happens.
+    pub(crate) fn take_oam_dma(&mut self) -> bool {
+        std::mem::take(&mut self.oam_dma)
+    }
+
+    /// Whether the APU or the cartridge is holding the IRQ line low. Unlike
+    /// the NMI this stays set until the device is acknowledged.
+    pub fn irq(&self) -> bool {
+        self.apu.irq() || self.cartridge.borrow().irq()
+    }
+
+    /// The 2KB of internal RAM, without mirrors
+    pub fn ram(&self) -> &[u8; RAM_SIZE] {
+        &self.cpu_vram
+    }
+
+    pub(crate) fn ram_mut(&mut self) -> &mut [u8; RAM_SIZE] {
+        &mut self.cpu_vram
+    }
+
+    /// The part of a PPU dot carried over to the next CPU cycle, which a
+    /// savestate needs to resume on the same dot
+    pub(crate) fn dot_remainder(&self) -> usize {
+        self.dot_remainder
+    }
+
+    pub(crate) fn set_dot_remainder(&mut self, remainder: usize) {
+        self.dot_remainder = remainder;
+    }
+
+    #[inline]
+    pub fn mem_read(&mut self, addr: u16) -> u8 {
+        let value = match addr {
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.read_register(addr & 0x0007),
+            APU_STATUS => self.apu.read_status(),
+            JOYPAD1 | JOYPAD2 => {
+                self.frame_counter.input_polled();
+                let port = (addr - JOYPAD1) as usize;
+                // Only the data bits are driven, the rest is open bus which
+                // usually still holds the high byte of the address
+                0x40 | (self.controllers[port].read() & 0b0001_1111)
+            }
+            _ => self.read(addr),
+        };
+        self.apply_read_cheats(addr, value)
+    }
+
+    /// What [`Bus::mem_read`] would return, without side effects like
+    /// clearing the PPU's vblank flag. Meant for debuggers and front-ends.
+    #[inline]
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.apply_read_cheats(addr, self.peek_raw(addr))
+    }
+
+    #[inline]
+    fn apply_read_cheats(&self, addr: u16, value: u8) -> u8 {
+        if self.cheats.is_empty() {
+            return value;
+        }
+        self.cheats.read(addr, value)
+    }
+
+    #[inline]
+    pub fn mem_write(&mut self, addr: u16, data: u8) {
+        if !self.cheats.is_empty() && !self.cheats.allows_write(addr) {
+            return;
+        }
+        self.write(addr, data);
+    }
+
+    /// Writes the values of enabled freeze cheats. Front-ends call this once
+    /// per frame.
+    pub fn apply_freeze_cheats(&mut self) {
+        let cheats = std::mem::take(&mut self.cheats);
+        for (addr, value) in cheats.frozen() {
+            self.write(addr, value);
+        }
+        self.cheats = cheats;
+    }
+
+    /// A read by the CPU or DMA, which some cartridges' registers react to
+    #[inline]
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            CARTRIDGE..=CARTRIDGE_END => self.cartridge.borrow_mut().cpu_read(addr),
+            _ => self.peek_raw(addr),
+        }
+    }
+
+    /// [`Bus::peek`] without cheats
+    #[inline]
+    pub(crate) fn peek_raw(&self, addr: u16) -> u8 {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.cpu_vram[(addr & 0x07FF) as usize],
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.peek_register(addr & 0x0007),
+            APU_STATUS => self.apu.peek_status(),
+            CARTRIDGE..=CARTRIDGE_END => self.cartridge.borrow().cpu_peek(addr),
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.cpu_vram[(addr & 0x07FF) as usize] = data,
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                self.ppu.write_register(addr & 0x0007, data)
+            }
+            APU_REGISTERS..=APU_REGISTERS_END | APU_STATUS | JOYPAD2 => {
+                self.apu.write_register(addr, data)
+            }
+            JOYPAD1 => {
+                for controller in &mut self.controllers {
+                    controller.write(data);
+                }
+            }
+            OAM_DMA => {
+                let mut page = [0; 256];
+                for (offset, value) in page.iter_mut().enumerate() {
+                    *value = self.read((data as u16) << 8 | offset as u16);
+                }
+                self.ppu.write_oam_dma(&page);
+                self.oam_dma = true;
+            }
+            CARTRIDGE..=CARTRIDGE_END => self.cartridge.borrow_mut().cpu_write(addr, data),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::{Button, InputEvent};
+
+    use super::*;
+
+    #[test]
+    fn test_ram_is_mirrored() {
+        let mut bus = Bus::default();
+        bus.mem_write(0x0012, 0xAB);
+        assert_eq!(bus.mem_read(0x0812), 0xAB);
+        assert_eq!(bus.mem_read(0x1812), 0xAB);
+
+        bus.mem_write(0x1FFF, 0xCD);
+        assert_eq!(bus.mem_read(0x07FF), 0xCD);
+    }
+
+    #[test]
+    fn test_ppu_registers_are_mirrored() {
+        let mut bus = Bus::default();
+        bus.mem_write(0x3FF8, 0x80);
+        assert_eq!(bus.ppu.ctrl.bits(), 0x80);
+    }
+
+    #[test]
+    fn test_frames_without_input_polls_lag() {
+        let mut bus = Bus::default();
+        // A frame is 89342 PPU dots, a bit under 29781 CPU cycles
+        let frame = |bus: &mut Bus| while !bus.tick(100) {};
+
+        frame(&mut bus);
+        bus.mem_read(0x4016);
+        frame(&mut bus);
+        bus.peek(0x4016);
+        frame(&mut bus);
+
+        assert_eq!(bus.frame_counter().frames(), 3);
+        assert_eq!(bus.frame_counter().lag_frames(), 2);
+    }
+
+    #[test]
+    fn test_region_timing() {
+        let mut bus = Bus::default();
+        bus.set_region(Region::Pal);
+        // 3.2 dots per cycle, the fraction carries over between ticks
+        bus.tick(3);
+        assert_eq!(bus.ppu.dot(), 9);
+        bus.tick(2);
+        assert_eq!(bus.ppu.dot(), 16);
+
+        bus.set_region(Region::Dendy);
+        bus.load_rom(&Rom::new(&crate::hardware::rom::test::ines(1, 1, 0, 0)).unwrap())
+            .unwrap();
+        assert_eq!(bus.ppu.region(), Region::Dendy);
+    }
+
+    #[test]
+    fn test_battery_ram() {
+        let mut bus = Bus::default();
+        assert_eq!(bus.battery_ram(), None);
+
+        bus.load_rom(&Rom::new(&crate::hardware::rom::test::ines(1, 1, 0b10, 0)).unwrap())
+            .unwrap();
+        bus.mem_write(0x6000, 0x42);
+        let ram = bus.battery_ram().unwrap();
+        assert_eq!(ram[0], 0x42);
+
+        bus.mem_write(0x6000, 0x00);
+        bus.load_battery_ram(&ram).unwrap();
+        assert_eq!(bus.mem_read(0x6000), 0x42);
+        assert!(matches!(
+            bus.load_battery_ram(&ram[..0x100]),
+            Err(EmuError::InvalidBatterySave(_))
+        ));
+    }
+
+    #[test]
+    fn test_controller_ports() {
+        let mut bus = Bus::default();
+        for button in [Button::A, Button::Start] {
+            bus.controllers[0].handle_event(InputEvent::Button {
+                button,
+                pressed: true,
+            });
+        }
+        bus.controllers[1].handle_event(InputEvent::Button {
+            button: Button::B,
+            pressed: true,
+        });
+
+        bus.mem_write(JOYPAD1, 1);
+        bus.mem_write(JOYPAD1, 0);
+        let port1: Vec<u8> = (0..4).map(|_| bus.mem_read(JOYPAD1) & 1).collect();
+        let port2: Vec<u8> = (0..4).map(|_| bus.mem_read(JOYPAD2) & 1).collect();
+        assert_eq!(port1, [1, 0, 0, 1]);
+        assert_eq!(port2, [0, 1, 0, 0]);
+        assert_eq!(bus.mem_read(JOYPAD1) & 0xE0, 0x40);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut bus = Bus::default();
+        for offset in 0..=0xFF {
+            bus.mem_write(0x0200 + offset, offset as u8);
+        }
+        bus.mem_write(0x4014, 0x02);
+        assert_eq!(bus.ppu.oam[0x00], 0x00);
+        assert_eq!(bus.ppu.oam[0xFF], 0xFF);
+    }
+
+    #[test]
+    fn test_prg_rom() {
+        let mut bus = Bus::default();
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[0x3FFC] = 0x34;
+        bus.load_prg_rom(prg_rom).unwrap();
+
+        // 16KB ROMs show up in both halves
+        assert_eq!(bus.mem_read(0xBFFC), 0x34);
+        assert_eq!(bus.mem_read(0xFFFC), 0x34);
+
+        bus.mem_write(0xFFFC, 0x00);
+        assert_eq!(bus.mem_read(0xFFFC), 0x34);
+    }
+}
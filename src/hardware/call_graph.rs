@@ -0,0 +1,170 @@
+use std::{collections::HashMap, fmt::Write};
+
+use serde::Serialize;
+
+/// A subroutine that has been entered but not returned from yet
+#[derive(Debug, Clone, Copy)]
+struct StackFrame {
+    caller: Option<u16>,
+    callee: u16,
+    /// Stack pointer before the return address was pushed, so returns can be
+    /// matched up even when code manipulates the stack directly
+    stack_pointer: u8,
+    start_cycles: u64,
+}
+
+/// Every call from one subroutine to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CallEdge {
+    /// Entry address of the calling subroutine, `None` for code that isn't
+    /// inside any tracked call, e.g. the reset handler's main loop
+    pub caller: Option<u16>,
+    pub callee: u16,
+    pub calls: u64,
+    /// Cycles spent inside the callee including everything it called
+    pub cycles: u64,
+    /// Whether the callee was entered through the NMI or IRQ vector
+    pub interrupt: bool,
+}
+
+/// Aggregates JSR/RTS and NMI/RTI pairs into a caller → callee graph, so
+/// homebrew developers can see where the frame budget goes per
+/// subroutine. Off by default.
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    enabled: bool,
+    stack: Vec<StackFrame>,
+    edges: HashMap<(Option<u16>, u16), CallEdge>,
+}
+
+impl CallGraph {
+    pub fn enabled() -> Self {
+        Self {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn clear(&mut self) {
+        self.stack.clear();
+        self.edges.clear();
+    }
+
+    /// Entry addresses of the subroutines currently being executed,
+    /// outermost first
+    pub fn call_stack(&self) -> impl Iterator<Item = u16> + '_ {
+        self.stack.iter().map(|frame| frame.callee)
+    }
+
+    /// Edges sorted by cycles spent, most expensive first
+    pub fn edges(&self) -> Vec<CallEdge> {
+        let mut edges: Vec<_> = self.edges.values().copied().collect();
+        edges.sort_by(|a, b| {
+            b.cycles
+                .cmp(&a.cycles)
+                .then(a.caller.cmp(&b.caller))
+                .then(a.callee.cmp(&b.callee))
+        });
+        edges
+    }
+
+    pub(crate) fn call(&mut self, callee: u16, stack_pointer: u8, cycles: u64, interrupt: bool) {
+        let caller = self.stack.last().map(|frame| frame.callee);
+        let edge = self.edges.entry((caller, callee)).or_insert(CallEdge {
+            caller,
+            callee,
+            calls: 0,
+            cycles: 0,
+            interrupt,
+        });
+        edge.calls += 1;
+        self.stack.push(StackFrame {
+            caller,
+            callee,
+            stack_pointer,
+            start_cycles: cycles,
+        });
+    }
+
+    /// Closes every frame the stack pointer has unwound past
+    pub(crate) fn ret(&mut self, stack_pointer: u8, cycles: u64) {
+        while let Some(frame) = self.stack.last()
+            && frame.stack_pointer <= stack_pointer
+        {
+            let frame = self.stack.pop().unwrap();
+            if let Some(edge) = self.edges.get_mut(&(frame.caller, frame.callee)) {
+                edge.cycles += cycles - frame.start_cycles;
+            }
+        }
+    }
+
+    /// Graphviz DOT with one node per subroutine and edges labelled with
+    /// call counts and cycles
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph calls {\n");
+        for edge in self.edges() {
+            let caller = match edge.caller {
+                Some(caller) => format!("${caller:04X}"),
+                None => "root".to_string(),
+            };
+            let style = if edge.interrupt { ", style=dashed" } else { "" };
+            let _ = writeln!(
+                dot,
+                "    \"{caller}\" -> \"${:04X}\" [label=\"{}x {} cycles\"{style}];",
+                edge.callee, edge.calls, edge.cycles
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.edges()).expect("call edges always serialize")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::CPU;
+
+    use super::*;
+
+    #[test]
+    fn test_nested_calls() {
+        let mut cpu = CPU::default();
+        cpu.call_graph = CallGraph::enabled();
+        // $0600: JSR $0607; JSR $0607; BRK
+        // $0607: JSR $060B; RTS
+        // $060B: RTS
+        cpu.load_and_run(&[
+            0x20, 0x07, 0x06, 0x20, 0x07, 0x06, 0x00, 0x20, 0x0b, 0x06, 0x60, 0x60,
+        ])
+        .unwrap();
+
+        let edges = cpu.call_graph.edges();
+        assert_eq!(edges.len(), 2);
+        assert_eq!(
+            edges[0],
+            CallEdge {
+                caller: None,
+                callee: 0x0607,
+                calls: 2,
+                // JSR 6 + (JSR 6 + RTS 6) + RTS 6, twice
+                cycles: 48,
+                interrupt: false,
+            }
+        );
+        assert_eq!(edges[1].caller, Some(0x0607));
+        assert_eq!(edges[1].callee, 0x060B);
+        assert_eq!(edges[1].cycles, 24);
+        assert_eq!(cpu.call_graph.call_stack().count(), 0);
+
+        let dot = cpu.call_graph.to_dot();
+        assert!(dot.contains("\"root\" -> \"$0607\" [label=\"2x 48 cycles\"];"));
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CheatId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Cheat {
+    /// Replace-on-read like a Game Genie: reads of `address` return `value`,
+    /// optionally only when the real value equals `compare`
+    Replace {
+        address: u16,
+        value: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        compare: Option<u8>,
+    },
+    /// Re-writes `value` to `address` every frame, and with `lock` also
+    /// drops the game's own writes so it never sees anything else
+    Freeze {
+        address: u16,
+        value: u8,
+        #[serde(default)]
+        lock: bool,
+    },
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: CheatId,
+    cheat: Cheat,
+    enabled: bool,
+}
+
+/// Active cheats, consulted by the [`Bus`](super::Bus) on every access
+#[derive(Debug, Default)]
+pub struct Cheats {
+    next_id: usize,
+    entries: Vec<Entry>,
+}
+
+impl Cheats {
+    /// Adds an enabled cheat
+    pub fn add(&mut self, cheat: Cheat) -> CheatId {
+        let id = CheatId(self.next_id);
+        self.next_id += 1;
+        self.entries.push(Entry {
+            id,
+            cheat,
+            enabled: true,
+        });
+        id
+    }
+
+    /// Returns whether the cheat existed
+    pub fn remove(&mut self, id: CheatId) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != len
+    }
+
+    /// Toggles a cheat without forgetting it. Returns whether it exists.
+    pub fn set_enabled(&mut self, id: CheatId, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_enabled(&self, id: CheatId) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.id == id && entry.enabled)
+    }
+
+    pub fn get(&self, id: CheatId) -> Option<Cheat> {
+        self.entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| entry.cheat)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &Cheat> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| &entry.cheat)
+    }
+
+    /// The value the CPU sees when `value` is read from `addr`
+    pub(crate) fn read(&self, addr: u16, value: u8) -> u8 {
+        self.enabled()
+            .find_map(|cheat| match *cheat {
+                Cheat::Replace {
+                    address,
+                    value: replacement,
+                    compare,
+                } if address == addr && compare.is_none_or(|compare| compare == value) => {
+                    Some(replacement)
+                }
+                _ => None,
+            })
+            .unwrap_or(value)
+    }
+
+    /// Whether a write to `addr` should reach memory
+    pub(crate) fn allows_write(&self, addr: u16) -> bool {
+        !self.enabled().any(
+            |cheat| matches!(*cheat, Cheat::Freeze { address, lock: true, .. } if address == addr),
+        )
+    }
+
+    /// Addresses and values of the enabled freeze cheats
+    pub(crate) fn frozen(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.enabled().filter_map(|cheat| match *cheat {
+            Cheat::Freeze { address, value, .. } => Some((address, value)),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hardware::Bus;
+
+    #[test]
+    fn test_replace_on_read() {
+        let mut bus = Bus::default();
+        bus.mem_write(0x0010, 0x03);
+        bus.cheats.add(Cheat::Replace {
+            address: 0x0010,
+            value: 0x09,
+            compare: Some(0x03),
+        });
+        assert_eq!(bus.mem_read(0x0010), 0x09);
+
+        // The compare value no longer matches
+        bus.mem_write(0x0010, 0x04);
+        assert_eq!(bus.mem_read(0x0010), 0x04);
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut bus = Bus::default();
+        let lives = bus.cheats.add(Cheat::Freeze {
+            address: 0x0020,
+            value: 0x05,
+            lock: false,
+        });
+        let health = bus.cheats.add(Cheat::Freeze {
+            address: 0x0021,
+            value: 0x10,
+            lock: true,
+        });
+
+        bus.apply_freeze_cheats();
+        bus.mem_write(0x0020, 0x04);
+        bus.mem_write(0x0021, 0x0F);
+        assert_eq!(bus.mem_read(0x0020), 0x04);
+        assert_eq!(bus.mem_read(0x0021), 0x10);
+
+        // Rewritten at the start of the next frame
+        bus.apply_freeze_cheats();
+        assert_eq!(bus.mem_read(0x0020), 0x05);
+
+        bus.cheats.set_enabled(health, false);
+        bus.mem_write(0x0021, 0x0F);
+        assert_eq!(bus.mem_read(0x0021), 0x0F);
+
+        assert!(bus.cheats.remove(lives));
+        assert!(!bus.cheats.remove(lives));
+    }
+}
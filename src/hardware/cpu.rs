@@ -1,11 +1,35 @@
 use crate::hardware::{
-    Gamepad,
+    Bus, CallGraph, EmuError, ExecutedInstruction, ExecutionHistory, MemoryWatches, Rom,
     opcode::{AddressingMode, CPU_OP_CODES, Instruction},
     status::CpuStatus,
 };
 
 const STACK_RESET: u8 = 0xFD;
 const STACK: u16 = 0x0100;
+const NMI_VECTOR: u16 = 0xFFFA;
+const IRQ_VECTOR: u16 = 0xFFFE;
+/// Where [`CPU::load`] puts bare programs
+const PROGRAM_START: u16 = 0x0600;
+
+/// What a single [`CPU::step`] did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepInfo {
+    pub instruction: ExecutedInstruction,
+    /// Cycles consumed, including servicing an interrupt and OAM DMA
+    pub cycles: u16,
+    /// Whether an NMI or IRQ was serviced before the instruction
+    pub interrupt: bool,
+    /// Whether the PPU finished a frame during the step
+    pub frame_done: bool,
+}
+
+impl StepInfo {
+    /// Bare programs end with BRK, so the run loops return once one has
+    /// been serviced
+    pub fn is_break(&self) -> bool {
+        matches!(self.instruction.op_code.instruction, Instruction::BRK)
+    }
+}
 
 #[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
@@ -15,7 +39,21 @@ pub struct CPU {
     pub status: CpuStatus,
     pub program_counter: u16,
     pub stack_pointer: u8,
-    memory: [u8; 0xFFFF],
+    /// Total cycles executed since power on
+    pub cycles: u64,
+    pub(crate) last_instruction: Option<ExecutedInstruction>,
+    pub watches: MemoryWatches,
+    pub history: ExecutionHistory,
+    pub call_graph: CallGraph,
+    pub bus: Bus,
+    /// Panic on errors instead of returning them, for debugging where the
+    /// backtrace is more useful than recovering
+    pub strict: bool,
+    /// Cycles the current instruction takes on top of its base cost, for
+    /// page crossings and taken branches
+    extra_cycles: u8,
+    /// Whether the last indexed address crossed a page
+    page_crossed: bool,
 }
 
 impl Default for CPU {
@@ -27,7 +65,15 @@ impl Default for CPU {
             status: CpuStatus::from_bits_truncate(0b100100),
             program_counter: 0,
             stack_pointer: STACK_RESET,
-            memory: [0; 0xFFFF],
+            cycles: 0,
+            last_instruction: None,
+            watches: MemoryWatches::default(),
+            history: ExecutionHistory::default(),
+            call_graph: CallGraph::default(),
+            bus: Bus::default(),
+            strict: false,
+            extra_cycles: 0,
+            page_crossed: false,
         }
     }
 }
@@ -39,30 +85,50 @@ impl CPU {
         self.register_y = 0;
         self.stack_pointer = STACK_RESET;
         self.status = CpuStatus::from_bits_truncate(0b100100);
+        self.last_instruction = None;
 
         self.program_counter = self.mem_read_u16(0xFFFC);
     }
 
-    pub fn mem_read(&self, addr: u16) -> u8 {
-        self.memory[addr as usize]
+    #[inline]
+    pub fn mem_read(&mut self, addr: u16) -> u8 {
+        self.bus.mem_read(addr)
+    }
+
+    /// Reads memory without side effects, see [`Bus::peek`]
+    #[inline]
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
     }
 
+    #[inline]
     pub fn mem_write(&mut self, addr: u16, data: u8) {
-        self.memory[addr as usize] = data;
+        if !self.watches.is_empty() && MemoryWatches::watches_addr(addr) {
+            let frame = self.bus.frame_counter().frames();
+            self.watches
+                .check(addr, self.bus.peek_raw(addr), data, frame);
+        }
+        if self.history.is_recording() && addr < 0x2000 {
+            self.history
+                .record_write(addr, self.bus.ram()[(addr & 0x07FF) as usize]);
+        }
+        self.bus.mem_write(addr, data);
     }
 
+    #[inline]
     // Returns the memory at position as little endian
-    pub fn mem_read_u16(&self, pos: u16) -> u16 {
+    pub fn mem_read_u16(&mut self, pos: u16) -> u16 {
         let lo = self.mem_read(pos);
-        let hi = self.mem_read(pos + 1);
+        let hi = self.mem_read(pos.wrapping_add(1));
         u16::from_be_bytes([hi, lo])
     }
 
     // Writes the data as correct little endian into memory
+    #[inline]
     pub fn mem_write_u16(&mut self, pos: u16, data: u16) {
         let le_bits = data.to_le_bytes();
         self.mem_write(pos, le_bits[0]);
-        self.mem_write(pos + 1, le_bits[1]);
+        self.mem_write(pos.wrapping_add(1), le_bits[1]);
     }
 
     fn stack_push(&mut self, value: u8) {
@@ -70,10 +136,11 @@ impl CPU {
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
+    // High byte first, so the address reads as little endian on the stack
     fn stack_push_u16(&mut self, value: u16) {
         let le_bits = value.to_le_bytes();
-        self.stack_push(le_bits[0]);
         self.stack_push(le_bits[1]);
+        self.stack_push(le_bits[0]);
     }
 
     fn stack_pop(&mut self) -> u8 {
@@ -85,18 +152,36 @@ impl CPU {
         let lo = self.stack_pop();
         let hi = self.stack_pop();
 
-        u16::from_le_bytes([hi, lo])
+        u16::from_le_bytes([lo, hi])
     }
 
-    pub fn load_and_run(&mut self, program: &[u8]) {
-        self.load(program);
+    pub fn load_and_run(&mut self, program: &[u8]) -> Result<(), EmuError> {
+        self.load(program)?;
         self.reset();
-        self.run();
+        self.run()
     }
 
-    pub fn load(&mut self, program: &[u8]) {
-        self.memory[0x0600..(0x0600 + program.len())].copy_from_slice(program);
-        self.mem_write_u16(0xFFFC, 0x0600);
+    /// Inserts a cartridge. Call [`CPU::reset`] afterwards to start it.
+    pub fn load_rom(&mut self, rom: &Rom) -> Result<(), EmuError> {
+        self.bus.load_rom(rom)
+    }
+
+    /// Copies a bare program into RAM at 0x0600 and points the reset vector
+    /// at it
+    pub fn load(&mut self, program: &[u8]) -> Result<(), EmuError> {
+        let max = self.bus.ram().len() - PROGRAM_START as usize;
+        if program.len() > max {
+            return self.fail(EmuError::ProgramTooLarge {
+                len: program.len(),
+                max,
+            });
+        }
+        let start = PROGRAM_START as usize;
+        self.bus.ram_mut()[start..start + program.len()].copy_from_slice(program);
+
+        let mut prg_rom = vec![0; 0x8000];
+        prg_rom[0x7FFC..0x7FFE].copy_from_slice(&PROGRAM_START.to_le_bytes());
+        self.bus.load_prg_rom(prg_rom)
     }
 
     fn add_to_register_a(&mut self, data: u8) {
@@ -121,21 +206,24 @@ impl CPU {
         self.set_register_a(result);
     }
 
+    #[inline]
     fn set_register_a(&mut self, value: u8) {
         self.register_a = value;
         self.status.update_zero_and_negative_flags(self.register_a);
     }
+    #[inline]
     fn set_register_x(&mut self, value: u8) {
         self.register_x = value;
         self.status.update_zero_and_negative_flags(self.register_x);
     }
+    #[inline]
     fn set_register_y(&mut self, value: u8) {
         self.register_y = value;
         self.status.update_zero_and_negative_flags(self.register_y);
     }
 
-    fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
-        match mode {
+    fn get_operand_address(&mut self, mode: &AddressingMode) -> Result<u16, EmuError> {
+        let addr = match mode {
             AddressingMode::Immediate => self.program_counter,
             AddressingMode::ZeroPage => self.mem_read(self.program_counter) as u16,
             AddressingMode::ZeroPageX => {
@@ -149,11 +237,11 @@ impl CPU {
             AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
             AddressingMode::AbsoluteX => {
                 let base = self.mem_read_u16(self.program_counter);
-                base.wrapping_add(self.register_x as u16)
+                self.indexed(base, self.register_x)
             }
             AddressingMode::AbsoluteY => {
                 let base = self.mem_read_u16(self.program_counter);
-                base.wrapping_add(self.register_y as u16)
+                self.indexed(base, self.register_y)
             }
             AddressingMode::IndirectX => {
                 let base = self.mem_read(self.program_counter);
@@ -171,15 +259,25 @@ impl CPU {
                 let hi = self.mem_read(base.wrapping_add(1) as u16);
 
                 let deref_base = u16::from_be_bytes([hi, lo]);
-                deref_base.wrapping_add(self.register_y as u16)
+                self.indexed(deref_base, self.register_y)
             }
             AddressingMode::Other => {
-                panic!("mode {:?} not supported", mode)
+                return self.fail(EmuError::InvalidAddressingMode {
+                    mode: *mode,
+                    address: self.program_counter.wrapping_sub(1),
+                });
             }
-        }
+        };
+        Ok(addr)
     }
 
-    fn get_relative_offset(&self) -> u16 {
+    fn indexed(&mut self, base: u16, index: u8) -> u16 {
+        let addr = base.wrapping_add(index as u16);
+        self.page_crossed = page_crossed(base, addr);
+        addr
+    }
+
+    fn get_relative_offset(&mut self) -> u16 {
         let jump = self.mem_read(self.program_counter) as i8;
 
         self.program_counter
@@ -187,371 +285,554 @@ impl CPU {
             .wrapping_add(jump as u16)
     }
 
-    fn compare(&mut self, mode: &AddressingMode, data: u8) {
-        let addr = self.get_operand_address(mode);
+    /// Taken branches cost a cycle, and another if they land on a new page
+    fn branch(&mut self, condition: bool) {
+        if !condition {
+            return;
+        }
+        let target = self.get_relative_offset();
+        self.extra_cycles += 1;
+        if page_crossed(self.program_counter.wrapping_add(1), target) {
+            self.extra_cycles += 1;
+        }
+        self.program_counter = target;
+    }
+
+    /// Services a non-maskable interrupt right away, as if the PPU had
+    /// requested one
+    pub fn nmi(&mut self) {
+        self.interrupt(NMI_VECTOR);
+    }
+
+    /// Services an interrupt request unless the I flag masks it. Returns
+    /// whether it was taken.
+    pub fn irq(&mut self) -> bool {
+        if self.status.contains(CpuStatus::INTERRUPT) {
+            return false;
+        }
+        self.interrupt(IRQ_VECTOR);
+        true
+    }
+
+    /// Pushes the return address and status and jumps through the NMI or
+    /// IRQ `vector`, taking 7 cycles. Returns whether the PPU finished a
+    /// frame meanwhile.
+    fn interrupt(&mut self, vector: u16) -> bool {
+        self.stack_push_u16(self.program_counter);
+        let mut status = self.status;
+        status.remove(CpuStatus::BREAK);
+        self.stack_push(status.bits() | 0b0010_0000);
+        self.status.insert(CpuStatus::INTERRUPT);
+
+        self.program_counter = self.mem_read_u16(vector);
+        if self.call_graph.is_enabled() {
+            self.call_graph.call(
+                self.program_counter,
+                self.stack_pointer.wrapping_add(3),
+                self.cycles,
+                true,
+            );
+        }
+        self.cycles += 7;
+        self.bus.tick(7)
+    }
+
+    fn compare(&mut self, mode: &AddressingMode, data: u8) -> Result<(), EmuError> {
+        let addr = self.get_operand_address(mode)?;
         let value = self.mem_read(addr);
+        self.compare_value(data, value);
+        Ok(())
+    }
 
+    fn compare_value(&mut self, data: u8, value: u8) {
         self.status.set(CpuStatus::CARRY, data >= value);
         self.status
             .update_zero_and_negative_flags(data.wrapping_sub(value));
     }
 
-    pub fn run_with_callback<F>(&mut self, mut callback: F)
-    where
-        F: FnMut(&mut CPU),
-    {
-        use Instruction::*;
-        loop {
-            let opscode = self.mem_read(self.program_counter);
-            self.program_counter += 1;
-
-            let program_counter_state = self.program_counter;
-            let command = CPU_OP_CODES
-                .get(&opscode)
-                .unwrap_or_else(|| panic!("Expected valid opcode: {opscode:X?}"));
-
-            match &command.instruction {
-                ADC => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let value = self.mem_read(addr);
-                    self.add_to_register_a(value);
-                }
-                ASL => {
-                    let accumulator = matches!(command.addressing_mode, AddressingMode::Other);
-                    let (addr, mut value) = if accumulator {
-                        (0, self.register_a)
-                    } else {
-                        let addr = self.get_operand_address(&command.addressing_mode);
-                        (addr, self.mem_read(addr))
-                    };
-
-                    self.status.set(CpuStatus::CARRY, value >> 7 == 1);
-
-                    value <<= 1;
-
-                    if accumulator {
-                        self.set_register_a(value);
-                    } else {
-                        self.mem_write(addr, value);
-                        self.status.update_zero_and_negative_flags(value);
-                    }
-                }
-                AND => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let value = self.mem_read(addr);
-                    self.set_register_a(self.register_a & value);
-                }
-                BCC => {
-                    if !self.status.contains(CpuStatus::CARRY) {
-                        self.program_counter = self.get_relative_offset();
-                    }
-                }
-                BCS => {
-                    if self.status.contains(CpuStatus::CARRY) {
-                        self.program_counter = self.get_relative_offset()
-                    }
-                }
-                BEQ => {
-                    if self.status.contains(CpuStatus::ZERO) {
-                        self.program_counter = self.get_relative_offset()
-                    }
-                }
-                BIT => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let value = self.mem_read(addr);
-
-                    self.status
-                        .set(CpuStatus::ZERO, self.register_a & value == 0);
-                    self.status.set(CpuStatus::NEGATIVE, 0b10000000 > 0);
-                    self.status.set(CpuStatus::OVERFLOW, 0b01000000 > 0);
-                }
-                BMI => {
-                    if self.status.contains(CpuStatus::NEGATIVE) {
-                        self.program_counter = self.get_relative_offset();
-                    }
-                }
-                BNE => {
-                    if !self.status.contains(CpuStatus::ZERO) {
-                        self.program_counter = self.get_relative_offset();
-                    }
-                }
-                BPL => {
-                    if !self.status.contains(CpuStatus::NEGATIVE) {
-                        self.program_counter = self.get_relative_offset();
-                    }
-                }
+    /// Applies `op` to the accumulator for implied addressing, otherwise to
+    /// the operand in memory. Returns the new value.
+    fn read_modify_write(
+        &mut self,
+        mode: &AddressingMode,
+        op: impl FnOnce(&mut Self, u8) -> u8,
+    ) -> Result<u8, EmuError> {
+        if matches!(mode, AddressingMode::Other) {
+            let value = op(self, self.register_a);
+            self.set_register_a(value);
+            return Ok(value);
+        }
 
-                BRK => {
-                    self.status.insert(CpuStatus::BREAK);
-                    return;
-                }
-                BVC => {
-                    if !self.status.contains(CpuStatus::OVERFLOW) {
-                        self.program_counter = self.get_relative_offset();
-                    }
-                }
-                BVS => {
-                    if self.status.contains(CpuStatus::OVERFLOW) {
-                        self.program_counter = self.get_relative_offset();
-                    }
-                }
-                CLC => {
-                    self.status.remove(CpuStatus::CARRY);
-                }
-                CLI => {
-                    self.status.remove(CpuStatus::INTERRUPT);
-                }
-                CLV => {
-                    self.status.remove(CpuStatus::OVERFLOW);
-                }
-                CMP => {
-                    self.compare(&command.addressing_mode, self.register_a);
-                }
-                CPX => {
-                    self.compare(&command.addressing_mode, self.register_x);
-                }
-                CPY => {
-                    self.compare(&command.addressing_mode, self.register_y);
-                }
-                DEC => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let mut value = self.mem_read(addr);
+        let addr = self.get_operand_address(mode)?;
+        let value = self.mem_read(addr);
+        let value = op(self, value);
+        self.mem_write(addr, value);
+        self.status.update_zero_and_negative_flags(value);
+        Ok(value)
+    }
 
-                    value = value.wrapping_sub(1);
-                    self.mem_write(addr, value);
-                    self.status.update_zero_and_negative_flags(value);
-                }
-                DEX => {
-                    let value = self.register_x.wrapping_sub(1);
-                    self.set_register_x(value);
-                }
-                DEY => {
-                    let value = self.register_y.wrapping_sub(1);
-                    self.set_register_y(value);
-                }
-                EOR => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let value = self.mem_read(addr);
+    fn shift_left(&mut self, value: u8) -> u8 {
+        self.status.set(CpuStatus::CARRY, value >> 7 == 1);
+        value << 1
+    }
 
-                    self.set_register_a(self.register_a ^ value);
-                }
-                INC => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let mut value = self.mem_read(addr);
+    fn shift_right(&mut self, value: u8) -> u8 {
+        self.status.set(CpuStatus::CARRY, value & 1 == 1);
+        value >> 1
+    }
 
-                    value = value.wrapping_add(1);
-                    self.mem_write(addr, value);
-                    self.status.update_zero_and_negative_flags(value);
-                }
-                INX => {
-                    self.set_register_x(self.register_x.wrapping_add(1));
-                }
-                INY => {
-                    self.set_register_y(self.register_y.wrapping_sub(1));
-                }
+    fn rotate_left(&mut self, value: u8) -> u8 {
+        let carry = self.status.contains(CpuStatus::CARRY) as u8;
+        self.status.set(CpuStatus::CARRY, value & 0x80 == 0x80);
+        value << 1 | carry
+    }
 
-                JMP => {
-                    let addr = match &command.addressing_mode {
-                        AddressingMode::Absolute => {
-                            self.get_operand_address(&command.addressing_mode)
-                        }
-                        AddressingMode::Other => {
-                            let addr = self.mem_read_u16(self.program_counter);
-                            if addr & 0x00FF == 0x00FF {
-                                let lo = self.mem_read(addr);
-                                let hi = self.mem_read(addr & 0xFF00);
-                                u16::from_be_bytes([hi, lo])
-                            } else {
-                                self.mem_read_u16(addr)
-                            }
-                        }
+    fn rotate_right(&mut self, value: u8) -> u8 {
+        let carry = (self.status.contains(CpuStatus::CARRY) as u8) << 7;
+        self.status.set(CpuStatus::CARRY, value & 1 == 1);
+        value >> 1 | carry
+    }
 
-                        _ => unreachable!(),
-                    };
-                    self.program_counter = addr;
-                }
-                JSR => {
-                    self.stack_push_u16(self.program_counter + 2 - 1);
-                    let target_address = self.mem_read_u16(self.program_counter);
-                    self.program_counter = target_address;
-                }
-                LDA => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let value = self.mem_read(addr);
-                    self.set_register_a(value);
-                }
-                LDX => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let value = self.mem_read(addr);
-                    self.set_register_x(value);
-                }
-                LDY => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let value = self.mem_read(addr);
-                    self.set_register_y(value);
-                }
-                LSR => {
-                    let accumulator = matches!(command.addressing_mode, AddressingMode::Other);
-                    let (addr, mut value) = if accumulator {
-                        (0, self.register_a)
-                    } else {
-                        let addr = self.get_operand_address(&command.addressing_mode);
-                        (addr, self.mem_read(addr))
-                    };
-
-                    self.status.set(CpuStatus::CARRY, value & 1 == 1);
-
-                    value >>= 1;
-
-                    if accumulator {
-                        self.set_register_a(value);
-                    } else {
-                        self.mem_write(addr, value);
-                        self.status.update_zero_and_negative_flags(value);
+    /// Reads the operand, for instructions that only need the value
+    fn operand(&mut self, mode: &AddressingMode) -> Result<u8, EmuError> {
+        let addr = self.get_operand_address(mode)?;
+        Ok(self.mem_read(addr))
+    }
+
+    /// Hands `err` back to the host, or panics with it in strict mode
+    fn fail<T>(&self, err: EmuError) -> Result<T, EmuError> {
+        if self.strict {
+            panic!("{err}");
+        }
+        Err(err)
+    }
+
+    /// Executes exactly one instruction, servicing a pending NMI or, unless
+    /// interrupts are disabled, IRQ first.
+    /// Errors leave the program counter on the instruction that caused them.
+    pub fn step(&mut self) -> Result<StepInfo, EmuError> {
+        use Instruction::*;
+        let start_cycles = self.cycles;
+        let mut frame_done = false;
+        if self.history.is_recording() {
+            self.history.begin(self.snapshot());
+        }
+        let interrupt = if self.bus.poll_nmi() {
+            frame_done |= self.interrupt(NMI_VECTOR);
+            true
+        } else if self.bus.irq() && !self.status.contains(CpuStatus::INTERRUPT) {
+            frame_done |= self.interrupt(IRQ_VECTOR);
+            true
+        } else {
+            false
+        };
+
+        if !self.bus.is_mapped(self.program_counter) {
+            return self.fail(EmuError::UnmappedAddress(self.program_counter));
+        }
+        let opscode = self.mem_read(self.program_counter);
+        let Some(command) = CPU_OP_CODES.get(opscode) else {
+            return self.fail(EmuError::UnknownOpcode {
+                opcode: opscode,
+                address: self.program_counter,
+            });
+        };
+        self.program_counter = self.program_counter.wrapping_add(1);
+
+        let program_counter_state = self.program_counter;
+
+        self.extra_cycles = 0;
+        self.page_crossed = false;
+        let executed = ExecutedInstruction {
+            address: program_counter_state.wrapping_sub(1),
+            op_code: command,
+        };
+        self.last_instruction = Some(executed);
+
+        match &command.instruction {
+            ADC => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                let value = self.mem_read(addr);
+                self.add_to_register_a(value);
+            }
+            ASL => {
+                self.read_modify_write(&command.addressing_mode, Self::shift_left)?;
+            }
+            AND => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                let value = self.mem_read(addr);
+                self.set_register_a(self.register_a & value);
+            }
+            BCC => self.branch(!self.status.contains(CpuStatus::CARRY)),
+            BCS => self.branch(self.status.contains(CpuStatus::CARRY)),
+            BEQ => self.branch(self.status.contains(CpuStatus::ZERO)),
+            BIT => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                let value = self.mem_read(addr);
+
+                self.status
+                    .set(CpuStatus::ZERO, self.register_a & value == 0);
+                self.status
+                    .set(CpuStatus::NEGATIVE, value & 0b10000000 != 0);
+                self.status
+                    .set(CpuStatus::OVERFLOW, value & 0b01000000 != 0);
+            }
+            BMI => self.branch(self.status.contains(CpuStatus::NEGATIVE)),
+            BNE => self.branch(!self.status.contains(CpuStatus::ZERO)),
+            BPL => self.branch(!self.status.contains(CpuStatus::NEGATIVE)),
+
+            BRK => {
+                // The byte after BRK is padding, RTI returns past it. Only
+                // the pushed status tells BRK and IRQ handlers apart.
+                self.stack_push_u16(self.program_counter.wrapping_add(1));
+                self.stack_push(self.status.bits() | 0b0011_0000);
+                self.status.insert(CpuStatus::INTERRUPT);
+                self.program_counter = self.mem_read_u16(IRQ_VECTOR);
+            }
+            BVC => self.branch(!self.status.contains(CpuStatus::OVERFLOW)),
+            BVS => self.branch(self.status.contains(CpuStatus::OVERFLOW)),
+            CLC => {
+                self.status.remove(CpuStatus::CARRY);
+            }
+            CLD => {
+                self.status.remove(CpuStatus::DECIMAL_MODE);
+            }
+            CLI => {
+                self.status.remove(CpuStatus::INTERRUPT);
+            }
+            CLV => {
+                self.status.remove(CpuStatus::OVERFLOW);
+            }
+            CMP => {
+                self.compare(&command.addressing_mode, self.register_a)?;
+            }
+            CPX => {
+                self.compare(&command.addressing_mode, self.register_x)?;
+            }
+            CPY => {
+                self.compare(&command.addressing_mode, self.register_y)?;
+            }
+            DEC => {
+                self.read_modify_write(&command.addressing_mode, |_, value| value.wrapping_sub(1))?;
+            }
+            DEX => {
+                let value = self.register_x.wrapping_sub(1);
+                self.set_register_x(value);
+            }
+            DEY => {
+                let value = self.register_y.wrapping_sub(1);
+                self.set_register_y(value);
+            }
+            EOR => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                let value = self.mem_read(addr);
+
+                self.set_register_a(self.register_a ^ value);
+            }
+            INC => {
+                self.read_modify_write(&command.addressing_mode, |_, value| value.wrapping_add(1))?;
+            }
+            INX => {
+                self.set_register_x(self.register_x.wrapping_add(1));
+            }
+            INY => {
+                self.set_register_y(self.register_y.wrapping_add(1));
+            }
+
+            JMP => {
+                let addr = match &command.addressing_mode {
+                    AddressingMode::Absolute => {
+                        self.get_operand_address(&command.addressing_mode)?
                     }
-                }
-                NOP => {}
-                ORA => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let value = self.mem_read(addr);
-                    self.set_register_a(self.register_a | value);
-                }
-                PHA => {
-                    self.stack_push(self.register_a);
-                }
-                PHP => {
-                    self.status.insert(CpuStatus::BREAK);
-                    self.stack_push(self.status.bits());
-                }
-                PLA => {
-                    let value = self.stack_pop();
-                    self.set_register_a(value);
-                }
-                PLP => {
-                    let value = self.stack_pop();
-                    self.status = CpuStatus::from_bits_truncate(value);
-                }
-                ROL => {
-                    let accumulator = matches!(command.addressing_mode, AddressingMode::Other);
-                    let (addr, mut value) = if accumulator {
-                        (0, self.register_a)
-                    } else {
-                        let addr = self.get_operand_address(&command.addressing_mode);
-                        (addr, self.mem_read(addr))
-                    };
-
-                    let carry: u8 = if self.status.contains(CpuStatus::CARRY) {
-                        1
-                    } else {
-                        0
-                    };
-
-                    self.status.set(CpuStatus::CARRY, value & 0x80 == 0x80);
-
-                    value <<= 1;
-                    value |= carry;
-
-                    if accumulator {
-                        self.set_register_a(value);
-                    } else {
-                        self.mem_write(addr, value);
-                        self.status.update_zero_and_negative_flags(value);
+                    AddressingMode::Other => {
+                        let addr = self.mem_read_u16(self.program_counter);
+                        if addr & 0x00FF == 0x00FF {
+                            let lo = self.mem_read(addr);
+                            let hi = self.mem_read(addr & 0xFF00);
+                            u16::from_be_bytes([hi, lo])
+                        } else {
+                            self.mem_read_u16(addr)
+                        }
                     }
-                }
 
-                ROR => {
-                    let accumulator = matches!(command.addressing_mode, AddressingMode::Other);
-                    let (addr, mut value) = if accumulator {
-                        (0, self.register_a)
-                    } else {
-                        let addr = self.get_operand_address(&command.addressing_mode);
-                        (addr, self.mem_read(addr))
-                    };
-
-                    let carry: u8 = if self.status.contains(CpuStatus::CARRY) {
-                        0x80
-                    } else {
-                        0
-                    };
-
-                    self.status.set(CpuStatus::CARRY, value & 1 == 1);
-
-                    value >>= 1;
-                    value |= carry;
-
-                    if accumulator {
-                        self.set_register_a(value);
-                    } else {
-                        self.mem_write(addr, value);
-                        self.status.update_zero_and_negative_flags(value);
-                    }
+                    _ => unreachable!(),
+                };
+                self.program_counter = addr;
+            }
+            JSR => {
+                self.stack_push_u16(self.program_counter.wrapping_add(1));
+                let target_address = self.mem_read_u16(self.program_counter);
+                self.program_counter = target_address;
+            }
+            LDA => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                let value = self.mem_read(addr);
+                self.set_register_a(value);
+            }
+            LDX => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                let value = self.mem_read(addr);
+                self.set_register_x(value);
+            }
+            LDY => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                let value = self.mem_read(addr);
+                self.set_register_y(value);
+            }
+            LSR => {
+                self.read_modify_write(&command.addressing_mode, Self::shift_right)?;
+            }
+            NOP => {
+                // Unofficial NOPs with operands still perform the read
+                if !matches!(command.addressing_mode, AddressingMode::Other) {
+                    self.operand(&command.addressing_mode)?;
                 }
+            }
+            ORA => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                let value = self.mem_read(addr);
+                self.set_register_a(self.register_a | value);
+            }
+            PHA => {
+                self.stack_push(self.register_a);
+            }
+            PHP => {
+                // The pushed copy has B and the unused bit 5 set
+                self.stack_push(self.status.bits() | 0b0011_0000);
+            }
+            PLA => {
+                let value = self.stack_pop();
+                self.set_register_a(value);
+            }
+            PLP => {
+                let value = self.stack_pop();
+                self.status = CpuStatus::from_bits_truncate(value);
+                self.status.remove(CpuStatus::BREAK);
+            }
+            ROL => {
+                self.read_modify_write(&command.addressing_mode, Self::rotate_left)?;
+            }
 
-                RTI => {
-                    let value = self.stack_pop();
-                    self.status = CpuStatus::from_bits_truncate(value);
+            ROR => {
+                self.read_modify_write(&command.addressing_mode, Self::rotate_right)?;
+            }
 
-                    self.program_counter = self.stack_pop_u16();
-                }
-                RTS => {
-                    self.program_counter = self.stack_pop_u16() + 1;
-                }
-                // A - B = A + (-B)
-                // -B = !B + 1
-                SBC => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    let data = self.mem_read(addr);
-                    self.add_to_register_a((data as i8).wrapping_neg().wrapping_sub(1) as u8);
-                }
-                SEC => {
-                    self.status.insert(CpuStatus::CARRY);
-                }
-                SEI => {
-                    self.status.insert(CpuStatus::INTERRUPT);
-                }
-                STA => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    self.mem_write(addr, self.register_a);
-                }
-                STX => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    self.mem_write(addr, self.register_x);
-                }
-                STY => {
-                    let addr = self.get_operand_address(&command.addressing_mode);
-                    self.mem_write(addr, self.register_y);
-                }
-                TAX => {
-                    self.set_register_x(self.register_a);
-                }
-                TAY => {
-                    self.set_register_y(self.register_a);
-                }
-                TSX => {
-                    let value = self.stack_pop();
-                    self.set_register_x(value);
-                }
-                TXA => {
-                    self.set_register_a(self.register_x);
-                }
-                TXS => {
-                    self.stack_push(self.register_x);
-                }
-                TYA => {
-                    self.set_register_a(self.register_y);
-                }
+            RTI => {
+                let value = self.stack_pop();
+                self.status = CpuStatus::from_bits_truncate(value);
+                self.status.remove(CpuStatus::BREAK);
+
+                self.program_counter = self.stack_pop_u16();
+            }
+            RTS => {
+                self.program_counter = self.stack_pop_u16().wrapping_add(1);
+            }
+            // A - B = A + (-B)
+            // -B = !B + 1
+            SBC => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                let data = self.mem_read(addr);
+                self.add_to_register_a((data as i8).wrapping_neg().wrapping_sub(1) as u8);
+            }
+            SEC => {
+                self.status.insert(CpuStatus::CARRY);
+            }
+            SED => {
+                self.status.insert(CpuStatus::DECIMAL_MODE);
+            }
+            SEI => {
+                self.status.insert(CpuStatus::INTERRUPT);
+            }
+            STA => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                self.mem_write(addr, self.register_a);
+            }
+            STX => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                self.mem_write(addr, self.register_x);
+            }
+            STY => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                self.mem_write(addr, self.register_y);
+            }
+            TAX => {
+                self.set_register_x(self.register_a);
+            }
+            TAY => {
+                self.set_register_y(self.register_a);
+            }
+            TSX => {
+                self.set_register_x(self.stack_pointer);
+            }
+            TXA => {
+                self.set_register_a(self.register_x);
+            }
+            TXS => {
+                self.stack_pointer = self.register_x;
+            }
+            TYA => {
+                self.set_register_a(self.register_y);
+            }
+
+            ALR => {
+                let value = self.operand(&command.addressing_mode)?;
+                let value = self.shift_right(self.register_a & value);
+                self.set_register_a(value);
+            }
+            ANC => {
+                let value = self.operand(&command.addressing_mode)?;
+                self.set_register_a(self.register_a & value);
+                self.status
+                    .set(CpuStatus::CARRY, self.status.contains(CpuStatus::NEGATIVE));
+            }
+            ARR => {
+                let value = self.operand(&command.addressing_mode)?;
+                let carry = (self.status.contains(CpuStatus::CARRY) as u8) << 7;
+                let value = (self.register_a & value) >> 1 | carry;
+                self.set_register_a(value);
+
+                let bit6 = value & 0b0100_0000 != 0;
+                let bit5 = value & 0b0010_0000 != 0;
+                self.status.set(CpuStatus::CARRY, bit6);
+                self.status.set(CpuStatus::OVERFLOW, bit6 ^ bit5);
             }
+            AXS => {
+                let value = self.operand(&command.addressing_mode)?;
+                let and = self.register_a & self.register_x;
+                self.status.set(CpuStatus::CARRY, and >= value);
+                self.set_register_x(and.wrapping_sub(value));
+            }
+            DCP => {
+                let value = self.read_modify_write(&command.addressing_mode, |_, value| {
+                    value.wrapping_sub(1)
+                })?;
+                self.compare_value(self.register_a, value);
+            }
+            ISB => {
+                let value = self.read_modify_write(&command.addressing_mode, |_, value| {
+                    value.wrapping_add(1)
+                })?;
+                self.add_to_register_a(!value);
+            }
+            LAX => {
+                let value = self.operand(&command.addressing_mode)?;
+                self.set_register_a(value);
+                self.register_x = value;
+            }
+            RLA => {
+                let value = self.read_modify_write(&command.addressing_mode, Self::rotate_left)?;
+                self.set_register_a(self.register_a & value);
+            }
+            RRA => {
+                let value = self.read_modify_write(&command.addressing_mode, Self::rotate_right)?;
+                self.add_to_register_a(value);
+            }
+            SAX => {
+                let addr = self.get_operand_address(&command.addressing_mode)?;
+                self.mem_write(addr, self.register_a & self.register_x);
+            }
+            SLO => {
+                let value = self.read_modify_write(&command.addressing_mode, Self::shift_left)?;
+                self.set_register_a(self.register_a | value);
+            }
+            SRE => {
+                let value = self.read_modify_write(&command.addressing_mode, Self::shift_right)?;
+                self.set_register_a(self.register_a ^ value);
+            }
+        }
 
-            if program_counter_state == self.program_counter {
-                self.program_counter += (command.len - 1) as u16;
+        if program_counter_state == self.program_counter {
+            self.program_counter = self.program_counter.wrapping_add((command.len - 1) as u16);
+        }
+
+        if self.page_crossed && has_page_cross_penalty(&command.instruction) {
+            self.extra_cycles += 1;
+        }
+        let mut cycles = (command.cycles() + self.extra_cycles) as u16;
+        if self.bus.take_oam_dma() {
+            // The copy waits for an even cycle to start on
+            cycles += 513 + ((self.cycles + cycles as u64) % 2) as u16;
+        }
+        self.cycles += cycles as u64;
+        frame_done |= self.bus.tick(cycles);
+        self.history.commit(executed);
+        if self.call_graph.is_enabled() {
+            match command.instruction {
+                JSR => self.call_graph.call(
+                    self.program_counter,
+                    self.stack_pointer.wrapping_add(2),
+                    self.cycles - cycles as u64,
+                    false,
+                ),
+                RTS | RTI => self.call_graph.ret(self.stack_pointer, self.cycles),
+                _ => {}
             }
-            callback(self);
         }
+
+        Ok(StepInfo {
+            instruction: executed,
+            cycles: (self.cycles - start_cycles) as u16,
+            interrupt,
+            frame_done,
+        })
     }
 
-    pub fn run(&mut self) {
-        self.run_with_callback(|_| {});
+    /// Steps until `done` says so, a BRK has been serviced or a watch hit
+    fn run_until<F>(&mut self, mut done: F) -> Result<(), EmuError>
+    where
+        F: FnMut(&mut CPU, &StepInfo) -> bool,
+    {
+        self.watches.clear_hit();
+        loop {
+            let step = self.step()?;
+            if step.is_break() || done(self, &step) || self.watches.hit().is_some() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs until BRK or a watch hit, calling `callback` after every
+    /// instruction
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> Result<(), EmuError>
+    where
+        F: FnMut(&mut CPU),
+    {
+        self.run_until(|cpu, _| {
+            callback(cpu);
+            false
+        })
+    }
+
+    pub fn run(&mut self) -> Result<(), EmuError> {
+        self.run_with_callback(|_| {})
     }
+
+    /// Runs until at least `target` cycles have executed since power on
+    pub fn run_until_cycles(&mut self, target: u64) -> Result<(), EmuError> {
+        if self.cycles >= target {
+            return Ok(());
+        }
+        self.run_until(|cpu, _| cpu.cycles >= target)
+    }
+
+    /// Runs until the PPU finishes the current frame
+    pub fn run_frame(&mut self) -> Result<(), EmuError> {
+        self.run_until(|_, step| step.frame_done)
+    }
+}
+
+fn page_crossed(a: u16, b: u16) -> bool {
+    a & 0xFF00 != b & 0xFF00
+}
+
+/// Reads that take an extra cycle when indexing crosses a page. Writes and
+/// read-modify-write instructions always pay for it in their base cost.
+fn has_page_cross_penalty(instruction: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instruction,
+        ADC | AND | CMP | EOR | LDA | LDX | LDY | ORA | SBC | LAX | NOP
+    )
 }
 
 #[cfg(test)]
@@ -561,7 +842,7 @@ mod test {
     #[test]
     fn test_0xa9_lda_immediate_load_data() {
         let mut cpu = CPU::default();
-        cpu.load_and_run(&[0xa9, 0x05, 0x00]);
+        cpu.load_and_run(&[0xa9, 0x05, 0x00]).unwrap();
         assert_eq!(cpu.register_a, 0x05);
         assert!(cpu.status & CpuStatus::ZERO == 0b00);
         assert!(cpu.status & CpuStatus::NEGATIVE == 0);
@@ -570,21 +851,21 @@ mod test {
     #[test]
     fn test_0xa9_lda_zero_flag() {
         let mut cpu = CPU::default();
-        cpu.load_and_run(&[0xa9, 0x00, 0x00]);
+        cpu.load_and_run(&[0xa9, 0x00, 0x00]).unwrap();
         assert!(cpu.status & CpuStatus::ZERO == 0b10);
     }
 
     #[test]
     fn test_lda_negative_flag() {
         let mut cpu = CPU::default();
-        cpu.load_and_run(&[0xa9, 0xA5, 0x00]);
+        cpu.load_and_run(&[0xa9, 0xA5, 0x00]).unwrap();
         assert!(cpu.status.contains(CpuStatus::NEGATIVE))
     }
 
     #[test]
     fn test_5_ops_working_together() {
         let mut cpu = CPU::default();
-        cpu.load_and_run(&[0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
+        cpu.load_and_run(&[0xa9, 0xc0, 0xaa, 0xe8, 0x00]).unwrap();
 
         assert_eq!(cpu.register_x, 0xc1)
     }
@@ -593,7 +874,8 @@ mod test {
     fn test_inx_overflow() {
         let mut cpu = CPU::default();
         // #[TODO] Use load() then reset() then modify for tests, then run()
-        cpu.load_and_run(&[0xa9, 255, 0xaa, 0xe8, 0xe8, 0x00]);
+        cpu.load_and_run(&[0xa9, 255, 0xaa, 0xe8, 0xe8, 0x00])
+            .unwrap();
 
         assert_eq!(cpu.register_x, 1)
     }
@@ -602,14 +884,14 @@ mod test {
     fn test_lda_from_memory() {
         let mut cpu = CPU::default();
         cpu.mem_write(0x10, 0x55);
-        cpu.load_and_run(&[0xa5, 0x10, 0x00]);
+        cpu.load_and_run(&[0xa5, 0x10, 0x00]).unwrap();
         assert_eq!(cpu.register_a, 0x55)
     }
 
     #[test]
     fn test_asl() {
         let mut cpu = CPU::default();
-        cpu.load_and_run(&[0xa9, 0b11111110, 0x0A, 0x00]);
+        cpu.load_and_run(&[0xa9, 0b11111110, 0x0A, 0x00]).unwrap();
 
         // Confirms that the bits were shifted correctly
         assert_eq!(cpu.register_a, 0b11111100);
@@ -622,7 +904,8 @@ mod test {
     fn test_rol() {
         let mut cpu = CPU::default();
         // Adds value to accumulator, sets the carry flag then runs the ROL Op
-        cpu.load_and_run(&[0xa9, 0b01111110, 0x38, 0x2A, 0x00]);
+        cpu.load_and_run(&[0xa9, 0b01111110, 0x38, 0x2A, 0x00])
+            .unwrap();
 
         // Confirms that bits were shifted correctly and that
         // the carry flag set bit 0 correctly
@@ -631,4 +914,321 @@ mod test {
         // Confirms that the carry flag copied the value from bit 7
         assert!(!cpu.status.contains(CpuStatus::CARRY))
     }
+
+    #[test]
+    fn test_page_cross_costs_a_cycle() {
+        let mut cpu = CPU::default();
+        cpu.load_and_run(&[0xa2, 0x01, 0xbd, 0x00, 0x06, 0x00])
+            .unwrap();
+        // Including the 7 for BRK
+        assert_eq!(cpu.cycles, 13);
+
+        let mut cpu = CPU::default();
+        cpu.load_and_run(&[0xa2, 0x01, 0xbd, 0xff, 0x06, 0x00])
+            .unwrap();
+        assert_eq!(cpu.cycles, 14);
+    }
+
+    #[test]
+    fn test_taken_branch_cycles() {
+        let mut cpu = CPU::default();
+        cpu.load_and_run(&[0xa2, 0x00, 0xf0, 0x00, 0x00]).unwrap();
+        assert_eq!(cpu.cycles, 12);
+
+        // Branching back onto the previous page
+        let mut cpu = CPU::default();
+        cpu.load_and_run(&[0xa2, 0x00, 0xf0, 0xf0, 0x00]).unwrap();
+        assert_eq!(cpu.last_instruction.unwrap().address, 0x05f4);
+        assert_eq!(cpu.cycles, 13);
+    }
+
+    #[test]
+    fn test_vblank_nmi() {
+        let mut cpu = CPU::default();
+        // LDA #$80; STA $2000; loop: JMP loop
+        cpu.load(&[0xa9, 0x80, 0x8d, 0x00, 0x20, 0x4c, 0x05, 0x06])
+            .unwrap();
+        let mut prg_rom = vec![0; 0x8000];
+        // The NMI handler at $0700 is a BRK, which stops the run
+        prg_rom[0x7FFA..0x7FFE].copy_from_slice(&[0x00, 0x07, 0x00, 0x06]);
+        cpu.bus.load_prg_rom(prg_rom).unwrap();
+        cpu.reset();
+        cpu.run().unwrap();
+
+        assert_eq!(cpu.last_instruction.unwrap().address, 0x0700);
+        assert_eq!(cpu.bus.ppu.scanline(), 241);
+        assert!(cpu.cycles >= 241 * 341 / 3);
+        assert!(cpu.status.contains(CpuStatus::INTERRUPT));
+    }
+
+    #[test]
+    fn test_iny_increments() {
+        let mut cpu = CPU::default();
+        cpu.load_and_run(&[0xa0, 0x7f, 0xc8, 0x00]).unwrap();
+        assert_eq!(cpu.register_y, 0x80);
+        assert!(cpu.status.contains(CpuStatus::NEGATIVE));
+    }
+
+    #[test]
+    fn test_stack_transfers() {
+        let mut cpu = CPU::default();
+        // LDX #$80; TXS; LDX #$00; TSX
+        cpu.load_and_run(&[0xa2, 0x80, 0x9a, 0xa2, 0x00, 0xba, 0x00])
+            .unwrap();
+        // Less the three bytes BRK pushed
+        assert_eq!(cpu.stack_pointer, 0x7d);
+        assert_eq!(cpu.register_x, 0x80);
+    }
+
+    #[test]
+    fn test_bit_copies_top_bits() {
+        let mut cpu = CPU::default();
+        cpu.mem_write(0x10, 0b0100_0000);
+        cpu.load_and_run(&[0xa9, 0x01, 0x24, 0x10, 0x00]).unwrap();
+        assert!(cpu.status.contains(CpuStatus::OVERFLOW));
+        assert!(!cpu.status.contains(CpuStatus::NEGATIVE));
+        assert!(cpu.status.contains(CpuStatus::ZERO));
+    }
+
+    #[test]
+    fn test_ror_and_decimal_flag() {
+        let mut cpu = CPU::default();
+        // SEC; LDA #$02; ROR A; SED
+        cpu.load_and_run(&[0x38, 0xa9, 0x02, 0x6a, 0xf8, 0x00])
+            .unwrap();
+        assert_eq!(cpu.register_a, 0x81);
+        assert!(cpu.status.contains(CpuStatus::DECIMAL_MODE));
+    }
+
+    #[test]
+    fn test_rts_to_pushed_address() {
+        let mut cpu = CPU::default();
+        // Jump to $0620 by pushing $061F high byte first, then RTS
+        cpu.load_and_run(&[0xa9, 0x06, 0x48, 0xa9, 0x1f, 0x48, 0x60])
+            .unwrap();
+        assert_eq!(cpu.last_instruction.unwrap().address, 0x0620);
+    }
+
+    #[test]
+    fn test_unofficial_lax_and_sax() {
+        let mut cpu = CPU::default();
+        cpu.mem_write(0x10, 0x3c);
+        // LAX $10; LDA #$0f; SAX $11
+        cpu.load_and_run(&[0xa7, 0x10, 0xa9, 0x0f, 0x87, 0x11, 0x00])
+            .unwrap();
+        assert_eq!(cpu.register_x, 0x3c);
+        assert_eq!(cpu.peek(0x11), 0x0c);
+    }
+
+    #[test]
+    fn test_unofficial_read_modify_write() {
+        let mut cpu = CPU::default();
+        cpu.mem_write(0x10, 0x06);
+        cpu.mem_write(0x11, 0x81);
+        // LDA #$05; DCP $10; SLO $11
+        cpu.load_and_run(&[0xa9, 0x05, 0xc7, 0x10, 0x07, 0x11, 0x00])
+            .unwrap();
+        assert_eq!(cpu.peek(0x10), 0x05);
+        assert_eq!(cpu.peek(0x11), 0x02);
+        assert_eq!(cpu.register_a, 0x07);
+        assert!(cpu.status.contains(CpuStatus::CARRY));
+    }
+
+    #[test]
+    fn test_unofficial_nops_skip_operands() {
+        let mut cpu = CPU::default();
+        // NOP $1234,X; NOP #$ff; NOP; LDA #$01
+        cpu.load_and_run(&[0x1c, 0x34, 0x12, 0x80, 0xff, 0x1a, 0xa9, 0x01, 0x00])
+            .unwrap();
+        assert_eq!(cpu.register_a, 0x01);
+    }
+
+    #[test]
+    fn test_unknown_opcode_is_an_error() {
+        let mut cpu = CPU::default();
+        // LDA #$01; KIL
+        let err = cpu.load_and_run(&[0xa9, 0x01, 0x02]).unwrap_err();
+        assert_eq!(
+            err,
+            EmuError::UnknownOpcode {
+                opcode: 0x02,
+                address: 0x0602
+            }
+        );
+        assert_eq!(cpu.program_counter, 0x0602);
+        assert_eq!(cpu.register_a, 0x01);
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown opcode 02 at 0600")]
+    fn test_strict_mode_panics() {
+        let mut cpu = CPU {
+            strict: true,
+            ..Default::default()
+        };
+        cpu.load_and_run(&[0x02]).unwrap();
+    }
+
+    #[test]
+    fn test_program_too_large() {
+        let mut cpu = CPU::default();
+        let err = cpu.load(&[0xea; 0x201]).unwrap_err();
+        assert_eq!(
+            err,
+            EmuError::ProgramTooLarge {
+                len: 0x201,
+                max: 0x200
+            }
+        );
+    }
+
+    #[test]
+    fn test_step() {
+        let mut cpu = CPU::default();
+        // LDA #$01; STA $0200,X
+        cpu.load(&[0xa9, 0x01, 0x9d, 0x00, 0x02]).unwrap();
+        cpu.reset();
+
+        let step = cpu.step().unwrap();
+        assert_eq!(step.instruction.address, 0x0600);
+        assert_eq!(step.cycles, 2);
+        assert!(!step.interrupt);
+        assert_eq!(cpu.register_a, 0x01);
+
+        let step = cpu.step().unwrap();
+        assert_eq!(step.cycles, 5);
+        assert_eq!(cpu.program_counter, 0x0605);
+        assert_eq!(cpu.cycles, 7);
+    }
+
+    #[test]
+    fn test_oam_dma_halts_cpu() {
+        let mut cpu = CPU::default();
+        // LDA #$02; STA $4014; STA $4014
+        cpu.load(&[0xa9, 0x02, 0x8d, 0x14, 0x40, 0x8d, 0x14, 0x40])
+            .unwrap();
+        cpu.reset();
+        cpu.mem_write(0x0203, 0x42);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 513);
+        assert_eq!(cpu.bus.ppu.oam[3], 0x42);
+        // Now it would start on an odd cycle, so it waits one more
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 514);
+        assert_eq!(cpu.cycles, 2 + 517 + 518);
+    }
+
+    #[test]
+    fn test_run_until_cycles_and_frame() {
+        let mut cpu = CPU::default();
+        // JMP $0600
+        cpu.load(&[0x4c, 0x00, 0x06]).unwrap();
+        cpu.reset();
+
+        cpu.run_until_cycles(10).unwrap();
+        assert_eq!(cpu.cycles, 12);
+
+        cpu.run_frame().unwrap();
+        assert_eq!(cpu.bus.ppu.frame(), 1);
+        cpu.run_frame().unwrap();
+        assert_eq!(cpu.bus.ppu.frame(), 2);
+    }
+
+    #[test]
+    fn test_irq_waits_for_cli() {
+        let mut cpu = CPU::default();
+        // SEI; JMP $0601
+        cpu.load(&[0x78, 0x4c, 0x01, 0x06]).unwrap();
+        cpu.reset();
+        cpu.run_until_cycles(40_000).unwrap();
+        assert!(cpu.bus.irq());
+        assert!((0x0601..0x0604).contains(&cpu.program_counter));
+
+        // CLI; JMP $0601, the APU frame IRQ then jumps through $FFFE to the
+        // BRK at $0000
+        cpu.load(&[0x58, 0x4c, 0x01, 0x06]).unwrap();
+        cpu.reset();
+        cpu.run().unwrap();
+        assert!(cpu.status.contains(CpuStatus::INTERRUPT));
+        assert_eq!(cpu.mem_read(0x01FD), 0x06);
+    }
+
+    #[test]
+    fn test_runs_from_prg_ram() {
+        let mut cpu = CPU::default();
+        cpu.bus.load_prg_rom(vec![0; 0x8000]).unwrap();
+        // LDA #$42 in the cartridge's RAM at $6000
+        cpu.mem_write(0x6000, 0xA9);
+        cpu.mem_write(0x6001, 0x42);
+        cpu.program_counter = 0x6000;
+        cpu.step().unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.program_counter, 0x6002);
+    }
+
+    #[test]
+    fn test_program_counter_wraps() {
+        let mut cpu = CPU::default();
+        let mut prg_rom = vec![0xEA; 0x8000];
+        // LDA # at $FFFF takes its operand from $0000
+        prg_rom[0x7FFF] = 0xA9;
+        cpu.bus.load_prg_rom(prg_rom).unwrap();
+        cpu.mem_write(0x0000, 0x42);
+        cpu.program_counter = 0xFFFF;
+        let step = cpu.step().unwrap();
+        assert_eq!(step.instruction.address, 0xFFFF);
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.program_counter, 0x0001);
+
+        // RTS to $FFFF + 1
+        cpu.mem_write(0x0001, 0x60);
+        cpu.stack_push_u16(0xFFFF);
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    #[test]
+    fn test_brk_is_a_software_interrupt() {
+        let mut cpu = CPU::default();
+        // BRK with a padding byte, then LDA #$42; BRK
+        cpu.load(&[0x00, 0xEA, 0xA9, 0x42, 0x00]).unwrap();
+        let mut prg_rom = vec![0; 0x8000];
+        // The IRQ/BRK handler at $0010 is an RTI
+        prg_rom[0x7FFC..0x7FFF].copy_from_slice(&[0x00, 0x06, 0x10]);
+        cpu.bus.load_prg_rom(prg_rom).unwrap();
+        cpu.mem_write(0x0010, 0x40);
+        cpu.reset();
+        cpu.run().unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0010);
+        assert!(cpu.status.contains(CpuStatus::INTERRUPT));
+        assert_eq!(cpu.stack_pointer, STACK_RESET - 3);
+        assert_eq!(cpu.mem_read(0x01FB) & 0b0011_0000, 0b0011_0000);
+        assert_eq!(cpu.mem_read_u16(0x01FC), 0x0602);
+
+        // RTI lands past the padding byte
+        cpu.run().unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+    }
+
+    #[test]
+    fn test_nmi_and_irq_entry_points() {
+        let mut cpu = CPU::default();
+        cpu.load(&[0xEA]).unwrap();
+        cpu.reset();
+
+        // Masked by the I flag reset sets
+        assert!(!cpu.irq());
+        assert_eq!(cpu.program_counter, 0x0600);
+
+        cpu.nmi();
+        assert_eq!(cpu.stack_pointer, STACK_RESET - 3);
+        // B clear, bit 5 set
+        assert_eq!(cpu.mem_read(0x01FB) & 0b0011_0000, 0b0010_0000);
+
+        cpu.status.remove(CpuStatus::INTERRUPT);
+        assert!(cpu.irq());
+        assert!(cpu.status.contains(CpuStatus::INTERRUPT));
+        assert_eq!(cpu.cycles, 14);
+    }
 }
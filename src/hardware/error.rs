@@ -0,0 +1,58 @@
+use std::fmt;
+
+use crate::hardware::AddressingMode;
+
+/// Something the emulated machine can't continue from. Hosts get these back
+/// from [`CPU::run`](crate::hardware::CPU::run) and friends instead of a
+/// panic, unless the CPU is in strict mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmuError {
+    /// The byte at `address` isn't an opcode the CPU implements
+    UnknownOpcode { opcode: u8, address: u16 },
+    /// An instruction tried to resolve an operand its addressing mode
+    /// doesn't have
+    InvalidAddressingMode { mode: AddressingMode, address: u16 },
+    /// Execution jumped to an address nothing is mapped at
+    UnmappedAddress(u16),
+    /// The cartridge image couldn't be parsed or isn't supported
+    InvalidRom(String),
+    /// The cartridge uses an iNES mapper that isn't implemented
+    UnsupportedMapper(u8),
+    /// A bare program is larger than the RAM it gets copied into
+    ProgramTooLarge { len: usize, max: usize },
+    /// A savestate is corrupt, from another build or for another game
+    InvalidSaveState(String),
+    /// Battery RAM from a `.sav` file doesn't fit the cartridge
+    InvalidBatterySave(String),
+    /// A file the emulator needs couldn't be read
+    Io(String),
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmuError::UnknownOpcode { opcode, address } => {
+                write!(f, "unknown opcode {opcode:02X} at {address:04X}")
+            }
+            EmuError::InvalidAddressingMode { mode, address } => {
+                write!(
+                    f,
+                    "addressing mode {mode:?} has no operand at {address:04X}"
+                )
+            }
+            EmuError::UnmappedAddress(address) => {
+                write!(f, "execution reached unmapped address {address:04X}")
+            }
+            EmuError::InvalidRom(reason) => write!(f, "invalid ROM: {reason}"),
+            EmuError::UnsupportedMapper(mapper) => write!(f, "mapper {mapper} is not supported"),
+            EmuError::ProgramTooLarge { len, max } => {
+                write!(f, "program is {len} bytes but only {max} fit")
+            }
+            EmuError::InvalidSaveState(reason) => write!(f, "invalid savestate: {reason}"),
+            EmuError::InvalidBatterySave(reason) => write!(f, "invalid save file: {reason}"),
+            EmuError::Io(reason) => write!(f, "I/O error: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for EmuError {}
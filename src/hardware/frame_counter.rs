@@ -0,0 +1,90 @@
+use std::{fmt, time::Duration};
+
+use crate::hardware::Region;
+
+/// Counts emulated frames and lag frames, i.e. frames where the game never
+/// read the controller ports, which is how speedrunners and TASers measure
+/// lost time
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCounter {
+    frames: u64,
+    lag_frames: u64,
+    polled: bool,
+    last_lagged: bool,
+    /// Sets the frame rate [`FrameCounter::elapsed`] converts with
+    region: Region,
+}
+
+impl FrameCounter {
+    pub(crate) fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub(crate) fn input_polled(&mut self) {
+        self.polled = true;
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.frames += 1;
+        self.last_lagged = !self.polled;
+        if self.last_lagged {
+            self.lag_frames += 1;
+        }
+        self.polled = false;
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn lag_frames(&self) -> u64 {
+        self.lag_frames
+    }
+
+    pub fn last_frame_lagged(&self) -> bool {
+        self.last_lagged
+    }
+
+    /// In-game time the frames add up to on real hardware
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.region.frame_rate())
+    }
+}
+
+/// e.g. `01:02.35 frame 3746 lag 12*`, where the `*` marks a lag frame
+impl fmt::Display for FrameCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let centis = self.elapsed().as_millis() / 10;
+        write!(
+            f,
+            "{:02}:{:02}.{:02} frame {} lag {}",
+            centis / 6000,
+            centis / 100 % 60,
+            centis % 100,
+            self.frames,
+            self.lag_frames
+        )?;
+        if self.last_lagged {
+            write!(f, "*")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lag_frames() {
+        let mut counter = FrameCounter::default();
+        counter.input_polled();
+        counter.end_frame();
+        assert!(!counter.last_frame_lagged());
+
+        counter.end_frame();
+        assert!(counter.last_frame_lagged());
+        assert_eq!((counter.frames(), counter.lag_frames()), (2, 1));
+        assert_eq!(counter.to_string(), "00:00.03 frame 2 lag 1*");
+    }
+}
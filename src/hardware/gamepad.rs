@@ -1,30 +1,159 @@
 use bitflags::bitflags;
 
-use crate::hardware::CPU;
-
-const GAMEPAD_ADDRESS: u8 = 0xFF;
+use crate::hardware::{Button, ControllerPort, InputEvent};
 
 bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    /// Button state of a standard controller, in the order the buttons are
+    /// shifted out when reading the port
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
     pub struct Gamepad: u8 {
-        const A         = 0b10000000;
-        const B         = 0b01000000;
-        const SELECT    = 0b00100000;
-        const START     = 0b00010000;
-        // const UP        = 0b00001000;
-        // const DOWN      = 0b00000100;
-        // const LEFT      = 0b00000010;
-        // const RIGHT     = 0b00000001;
-        const UP     = 0x77;
-        const DOWN   = 0x73;
-        const LEFT   = 0x61;
-        const RIGHT  = 0x64;
+        const A         = 0b00000001;
+        const B         = 0b00000010;
+        const SELECT    = 0b00000100;
+        const START     = 0b00001000;
+        const UP        = 0b00010000;
+        const DOWN      = 0b00100000;
+        const LEFT      = 0b01000000;
+        const RIGHT     = 0b10000000;
+    }
+}
+
+impl Gamepad {
+    pub fn from_button(button: Button) -> Option<Self> {
+        match button {
+            Button::A => Some(Gamepad::A),
+            Button::B => Some(Gamepad::B),
+            Button::Select => Some(Gamepad::SELECT),
+            Button::Start => Some(Gamepad::START),
+            Button::Up => Some(Gamepad::UP),
+            Button::Down => Some(Gamepad::DOWN),
+            Button::Left => Some(Gamepad::LEFT),
+            Button::Right => Some(Gamepad::RIGHT),
+            Button::Trigger => None,
+        }
+    }
+}
+
+/// The standard NES controller. Writing 1 to the strobe bit continuously
+/// reloads the shift register with the pressed buttons, writing 0 latches
+/// them so each read returns the next button, starting with A.
+#[derive(Debug, Default)]
+pub struct StandardController {
+    buttons: Gamepad,
+    strobe: bool,
+    shift: u8,
+}
+
+impl StandardController {
+    pub fn buttons(&self) -> Gamepad {
+        self.buttons
+    }
+}
+
+impl ControllerPort for StandardController {
+    fn handle_event(&mut self, event: InputEvent) {
+        if let InputEvent::Button { button, pressed } = event
+            && let Some(flag) = Gamepad::from_button(button)
+        {
+            self.buttons.set(flag, pressed);
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons.contains(Gamepad::A) as u8;
+        }
+        let bit = self.shift & 1;
+        // Official controllers return 1 once all eight buttons are read
+        self.shift = (self.shift >> 1) | 0x80;
+        bit
+    }
+
+    fn write(&mut self, data: u8) {
+        // Both the reload while high and the latch on the falling edge
+        // leave the current buttons in the shift register
+        self.strobe = data & 1 == 1;
+        self.shift = self.buttons.bits();
+    }
+}
+
+/// The input device of the snake demo: the last pressed direction as an
+/// ASCII `w`/`a`/`s`/`d` key code, which the program polls at $FF
+#[derive(Debug, Default)]
+pub struct SnakeKeypad {
+    last: u8,
+}
+
+impl SnakeKeypad {
+    /// Zero page address the snake program reads the key code from
+    pub const ADDRESS: u16 = 0xFF;
+}
+
+impl ControllerPort for SnakeKeypad {
+    fn handle_event(&mut self, event: InputEvent) {
+        if let InputEvent::Button {
+            button,
+            pressed: true,
+        } = event
+        {
+            self.last = match button {
+                Button::Up => b'w',
+                Button::Left => b'a',
+                Button::Down => b's',
+                Button::Right => b'd',
+                _ => return,
+            };
+        }
+    }
 
+    fn read(&mut self) -> u8 {
+        self.last
     }
 }
 
-impl CPU {
-    pub fn set_gamepad_button(&mut self, gamepad: Gamepad) {
-        self.mem_write(GAMEPAD_ADDRESS as u16, gamepad.bits());
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn press(button: Button) -> InputEvent {
+        InputEvent::Button {
+            button,
+            pressed: true,
+        }
+    }
+
+    #[test]
+    fn test_standard_controller_shifts_buttons() {
+        let mut pad = StandardController::default();
+        pad.handle_event(press(Button::A));
+        pad.handle_event(press(Button::Start));
+        pad.handle_event(press(Button::Right));
+
+        pad.write(1);
+        pad.write(0);
+        let reads: Vec<u8> = (0..10).map(|_| pad.read()).collect();
+        assert_eq!(reads, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_strobe_high_keeps_returning_a() {
+        let mut pad = StandardController::default();
+        pad.write(1);
+        assert_eq!(pad.read(), 0);
+        pad.handle_event(press(Button::A));
+        assert_eq!(pad.read(), 1);
+        assert_eq!(pad.read(), 1);
+    }
+
+    #[test]
+    fn test_snake_keypad_remembers_last_direction() {
+        let mut keypad = SnakeKeypad::default();
+        keypad.handle_event(press(Button::Left));
+        keypad.handle_event(press(Button::Start));
+        keypad.handle_event(InputEvent::Button {
+            button: Button::Left,
+            pressed: false,
+        });
+        assert_eq!(keypad.read(), b'a');
     }
 }
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+
+use crate::hardware::{CPU, CpuSnapshot, ExecutedInstruction};
+
+/// Most RAM writes a single instruction can make, counting an NMI that was
+/// serviced just before it
+const MAX_WRITES: usize = 8;
+
+/// An executed instruction with the registers from before it ran and the
+/// RAM it overwrote, enough to undo it
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryEntry {
+    pub before: CpuSnapshot,
+    pub instruction: ExecutedInstruction,
+    /// `(address, old value)` pairs in write order
+    writes: [(u16, u8); MAX_WRITES],
+    write_count: u8,
+}
+
+impl HistoryEntry {
+    pub fn writes(&self) -> &[(u16, u8)] {
+        &self.writes[..self.write_count as usize]
+    }
+}
+
+/// A bounded record of recently executed instructions, so a debugger can
+/// show how execution got somewhere and step backwards a little without
+/// any rewind machinery. Only internal RAM writes are undone; PPU and
+/// other I/O writes, and writes made from hooks, are not recorded.
+#[derive(Debug, Default)]
+pub struct ExecutionHistory {
+    capacity: usize,
+    entries: VecDeque<HistoryEntry>,
+    before: Option<CpuSnapshot>,
+    writes: [(u16, u8); MAX_WRITES],
+    write_count: u8,
+}
+
+impl ExecutionHistory {
+    /// Keeps the last `capacity` instructions. A capacity of 0, the
+    /// default, turns recording off.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn is_recording(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Oldest first
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn begin(&mut self, before: CpuSnapshot) {
+        self.before = Some(before);
+        self.write_count = 0;
+    }
+
+    pub(crate) fn record_write(&mut self, addr: u16, old: u8) {
+        if self.before.is_some() && (self.write_count as usize) < MAX_WRITES {
+            self.writes[self.write_count as usize] = (addr, old);
+            self.write_count += 1;
+        }
+    }
+
+    pub(crate) fn commit(&mut self, instruction: ExecutedInstruction) {
+        let Some(before) = self.before.take() else {
+            return;
+        };
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(HistoryEntry {
+            before,
+            instruction,
+            writes: self.writes,
+            write_count: self.write_count,
+        });
+    }
+
+    fn pop(&mut self) -> Option<HistoryEntry> {
+        self.entries.pop_back()
+    }
+}
+
+impl CPU {
+    /// Undoes the most recent recorded instruction, restoring the registers
+    /// and RAM it changed. Returns the undone entry.
+    pub fn step_back(&mut self) -> Option<HistoryEntry> {
+        let entry = self.history.pop()?;
+        for (addr, old) in entry.writes().iter().rev() {
+            self.bus.ram_mut()[(*addr & 0x07FF) as usize] = *old;
+        }
+
+        let before = &entry.before;
+        self.register_a = before.register_a;
+        self.register_x = before.register_x;
+        self.register_y = before.register_y;
+        self.status = before.status;
+        self.program_counter = before.program_counter;
+        self.stack_pointer = before.stack_pointer;
+        self.cycles = before.cycles;
+        self.last_instruction = before.last_instruction;
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_step_back() {
+        let mut cpu = CPU::default();
+        cpu.history = ExecutionHistory::with_capacity(3);
+        // LDA #$01; STA $10; LDA #$02; STA $10; BRK
+        cpu.load_and_run(&[0xa9, 0x01, 0x85, 0x10, 0xa9, 0x02, 0x85, 0x10, 0x00])
+            .unwrap();
+        assert_eq!(cpu.history.len(), 3);
+
+        let entry = cpu.step_back().unwrap();
+        assert_eq!(entry.instruction.address, 0x0608);
+        let entry = cpu.step_back().unwrap();
+        assert_eq!(entry.instruction.address, 0x0606);
+        assert_eq!(entry.writes(), [(0x10, 0x01)]);
+        assert_eq!(cpu.peek(0x10), 0x01);
+        assert_eq!(cpu.register_a, 0x02);
+        assert_eq!(cpu.program_counter, 0x0606);
+
+        cpu.step_back().unwrap();
+        assert_eq!(cpu.register_a, 0x01);
+        assert_eq!(cpu.program_counter, 0x0604);
+        // Older instructions fell out of the buffer
+        assert!(cpu.step_back().is_none());
+    }
+}
@@ -0,0 +1,44 @@
+/// A button on any of the supported controller types. Devices ignore the
+/// buttons they don't have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Zapper trigger or paddle button
+    Trigger,
+}
+
+/// An analog input, normalised to `0.0..=1.0`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// Horizontal position, e.g. where the Zapper is pointing or the paddle knob
+    X,
+    /// Vertical position, e.g. where the Zapper is pointing
+    Y,
+}
+
+/// A device-independent input event. Front-ends translate keyboard or
+/// gamepad input into these and leave it to the plugged in device to work
+/// out what the console should see.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Button { button: Button, pressed: bool },
+    Axis { axis: Axis, value: f32 },
+}
+
+/// A device plugged into one of the console's controller ports
+pub trait ControllerPort {
+    fn handle_event(&mut self, event: InputEvent);
+
+    /// The value the CPU sees when reading the port
+    fn read(&mut self) -> u8;
+
+    /// A CPU write to the port, e.g. the strobe bit of a standard controller
+    fn write(&mut self, _data: u8) {}
+}
@@ -0,0 +1,76 @@
+use crate::hardware::{CPU, CpuSnapshot, RAM_SIZE};
+
+/// A CPU+RAM copy for run-ahead and rewind. Buffers are allocated once up
+/// front so saving and restoring is just a couple of copies, separate from
+/// the full savestate format.
+pub struct LightweightState {
+    cpu: CpuSnapshot,
+    ram: Box<[u8; RAM_SIZE]>,
+}
+
+impl Default for LightweightState {
+    fn default() -> Self {
+        Self {
+            cpu: CpuSnapshot::default(),
+            ram: Box::new([0; RAM_SIZE]),
+        }
+    }
+}
+
+impl LightweightState {
+    /// The registers at the time of the last save
+    pub fn cpu(&self) -> &CpuSnapshot {
+        &self.cpu
+    }
+}
+
+impl CPU {
+    /// Overwrites `state` with the current registers and RAM
+    pub fn save_lightweight(&self, state: &mut LightweightState) {
+        state.cpu = self.snapshot();
+        state.ram.copy_from_slice(self.bus.ram());
+    }
+
+    /// Restores registers and RAM from `state`. Watches are debugger
+    /// configuration and are left alone.
+    pub fn load_lightweight(&mut self, state: &LightweightState) {
+        let cpu = &state.cpu;
+        self.register_a = cpu.register_a;
+        self.register_x = cpu.register_x;
+        self.register_y = cpu.register_y;
+        self.status = cpu.status;
+        self.program_counter = cpu.program_counter;
+        self.stack_pointer = cpu.stack_pointer;
+        self.cycles = cpu.cycles;
+        self.last_instruction = cpu.last_instruction;
+        self.bus.ram_mut().copy_from_slice(&state.ram[..]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_restore_lightweight_state() {
+        let mut cpu = CPU::default();
+        // LDA #$05; STA $10; BRK
+        cpu.load(&[0xa9, 0x05, 0x85, 0x10, 0x00]).unwrap();
+        cpu.reset();
+
+        let mut state = LightweightState::default();
+        cpu.save_lightweight(&mut state);
+        cpu.run().unwrap();
+        assert_eq!(cpu.mem_read(0x10), 0x05);
+
+        cpu.load_lightweight(&state);
+        assert_eq!(cpu.mem_read(0x10), 0x00);
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.program_counter, 0x0600);
+
+        // Running again from the restored state gives the same result
+        cpu.run().unwrap();
+        assert_eq!(cpu.mem_read(0x10), 0x05);
+        assert_eq!(cpu.register_a, 0x05);
+    }
+}
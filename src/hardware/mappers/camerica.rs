@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{EmuError, Mapper, Mirroring, Rom, decode_state, encode_state};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// Mapper 71: Camerica/Codemasters boards with a switchable 16KB bank at
+/// `$8000`, the last bank fixed at `$C000` and 8KB of CHR RAM. Fire Hawk's
+/// board also switches single-screen mirroring through `$9000..=$9FFF`.
+/// https://www.nesdev.org/wiki/INES_Mapper_071
+#[derive(Serialize, Deserialize)]
+pub struct Camerica {
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    chr_ram: Vec<u8>,
+    prg_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Camerica {
+    pub fn new(prg_rom: Vec<u8>, mirroring: Mirroring) -> Result<Self, EmuError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(EmuError::InvalidRom(format!(
+                "Camerica PRG ROM must be a multiple of 16KB, got {} bytes",
+                prg_rom.len()
+            )));
+        }
+        Ok(Self {
+            prg_rom,
+            chr_ram: vec![0; CHR_RAM_SIZE],
+            prg_bank: 0,
+            mirroring,
+        })
+    }
+
+    /// Codemasters boards only have CHR RAM, so any CHR ROM is ignored
+    pub fn from_rom(rom: &Rom) -> Result<Self, EmuError> {
+        Self::new(rom.prg_rom.clone(), rom.mirroring)
+    }
+}
+
+impl Mapper for Camerica {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank % banks,
+            0xC000..=0xFFFF => banks - 1,
+            _ => return 0,
+        };
+        self.prg_rom[bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE]
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x9000..=0x9FFF => {
+                self.mirroring = if data & 0x10 == 0 {
+                    Mirroring::SingleScreenLower
+                } else {
+                    Mirroring::SingleScreenUpper
+                };
+            }
+            0xC000..=0xFFFF => self.prg_bank = data as usize,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_ram[addr as usize % CHR_RAM_SIZE]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr_ram[addr as usize % CHR_RAM_SIZE] = data;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let saved: Self = decode_state(state)?;
+        *self = Self {
+            prg_rom: std::mem::take(&mut self.prg_rom),
+            ..saved
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prg_banks() {
+        let prg_rom = (0..4).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect();
+        let mut camerica = Camerica::new(prg_rom, Mirroring::Horizontal).unwrap();
+        assert_eq!(camerica.cpu_peek(0x8000), 0);
+        assert_eq!(camerica.cpu_peek(0xC000), 3);
+
+        camerica.cpu_write(0xC000, 2);
+        assert_eq!(camerica.cpu_peek(0xBFFF), 2);
+        assert_eq!(camerica.cpu_peek(0xFFFF), 3);
+
+        camerica.cpu_write(0x9000, 0x10);
+        assert_eq!(camerica.mirroring(), Mirroring::SingleScreenUpper);
+    }
+}
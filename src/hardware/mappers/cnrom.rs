@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{EmuError, Mapper, Mirroring, Rom, decode_state, encode_state};
+
+const PRG_ROM_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// Mapper 3: fixed 16KB or 32KB PRG ROM like NROM, with a register
+/// anywhere in `$8000..=$FFFF` selecting an 8KB CHR ROM bank.
+/// https://www.nesdev.org/wiki/CNROM
+#[derive(Serialize, Deserialize)]
+pub struct Cnrom {
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    #[serde(skip)]
+    chr_rom: Vec<u8>,
+    chr_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Cnrom {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Result<Self, EmuError> {
+        if prg_rom.len() != PRG_ROM_BANK_SIZE && prg_rom.len() != 2 * PRG_ROM_BANK_SIZE {
+            return Err(EmuError::InvalidRom(format!(
+                "CNROM PRG ROM must be 16KB or 32KB, got {} bytes",
+                prg_rom.len()
+            )));
+        }
+        if chr_rom.is_empty() || !chr_rom.len().is_multiple_of(CHR_BANK_SIZE) {
+            return Err(EmuError::InvalidRom(format!(
+                "CNROM CHR ROM must be a multiple of 8KB, got {} bytes",
+                chr_rom.len()
+            )));
+        }
+        Ok(Self {
+            prg_rom,
+            chr_rom,
+            chr_bank: 0,
+            mirroring,
+        })
+    }
+
+    pub fn from_rom(rom: &Rom) -> Result<Self, EmuError> {
+        Self::new(rom.prg_rom.clone(), rom.chr_rom.clone(), rom.mirroring)
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.prg_rom[(addr - 0x8000) as usize % self.prg_rom.len()],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x8000..=0xFFFF = addr {
+            // Bus conflict, see `Uxrom::cpu_write`
+            self.chr_bank = (data & self.cpu_peek(addr)) as usize;
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        let bank = self.chr_bank % (self.chr_rom.len() / CHR_BANK_SIZE);
+        self.chr_rom[bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE]
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let saved: Self = decode_state(state)?;
+        *self = Self {
+            prg_rom: std::mem::take(&mut self.prg_rom),
+            chr_rom: std::mem::take(&mut self.chr_rom),
+            ..saved
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chr_banks() {
+        let chr_rom = (0..4).flat_map(|bank| [bank; CHR_BANK_SIZE]).collect();
+        let mut cnrom = Cnrom::new(vec![0xFF; 0x8000], chr_rom, Mirroring::Vertical).unwrap();
+
+        cnrom.cpu_write(0x8000, 2);
+        assert_eq!(cnrom.ppu_read(0x0000), 2);
+        cnrom.cpu_write(0x8000, 3);
+        assert_eq!(cnrom.ppu_read(0x1FFF), 3);
+        assert_eq!(cnrom.cpu_peek(0xFFFF), 0xFF);
+    }
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{EmuError, Mapper, Mirroring, Rom, decode_state, encode_state};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// Mapper 11: Color Dreams boards, one register anywhere in `$8000..=$FFFF`
+/// selecting a 32KB PRG bank with the low bits and an 8KB CHR bank with the
+/// high nibble. https://www.nesdev.org/wiki/Color_Dreams
+#[derive(Serialize, Deserialize)]
+pub struct ColorDreams {
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    #[serde(skip)]
+    chr_rom: Vec<u8>,
+    prg_bank: usize,
+    chr_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl ColorDreams {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Result<Self, EmuError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(EmuError::InvalidRom(format!(
+                "Color Dreams PRG ROM must be a multiple of 32KB, got {} bytes",
+                prg_rom.len()
+            )));
+        }
+        if chr_rom.is_empty() || !chr_rom.len().is_multiple_of(CHR_BANK_SIZE) {
+            return Err(EmuError::InvalidRom(format!(
+                "Color Dreams CHR ROM must be a multiple of 8KB, got {} bytes",
+                chr_rom.len()
+            )));
+        }
+        Ok(Self {
+            prg_rom,
+            chr_rom,
+            prg_bank: 0,
+            chr_bank: 0,
+            mirroring,
+        })
+    }
+
+    pub fn from_rom(rom: &Rom) -> Result<Self, EmuError> {
+        Self::new(rom.prg_rom.clone(), rom.chr_rom.clone(), rom.mirroring)
+    }
+}
+
+impl Mapper for ColorDreams {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank % (self.prg_rom.len() / PRG_BANK_SIZE);
+                self.prg_rom[bank * PRG_BANK_SIZE + (addr - 0x8000) as usize]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x8000..=0xFFFF = addr {
+            self.prg_bank = (data & 0x03) as usize;
+            self.chr_bank = (data >> 4) as usize;
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        let bank = self.chr_bank % (self.chr_rom.len() / CHR_BANK_SIZE);
+        self.chr_rom[bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE]
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let saved: Self = decode_state(state)?;
+        *self = Self {
+            prg_rom: std::mem::take(&mut self.prg_rom),
+            chr_rom: std::mem::take(&mut self.chr_rom),
+            ..saved
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prg_and_chr_banks() {
+        let prg_rom = (0..2).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect();
+        let chr_rom = (0..4).flat_map(|bank| [bank; CHR_BANK_SIZE]).collect();
+        let mut color_dreams = ColorDreams::new(prg_rom, chr_rom, Mirroring::Vertical).unwrap();
+
+        color_dreams.cpu_write(0xFFF0, 0x31);
+        assert_eq!(color_dreams.cpu_peek(0x8000), 1);
+        assert_eq!(color_dreams.ppu_read(0x1FFF), 3);
+    }
+}
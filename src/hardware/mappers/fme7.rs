@@ -0,0 +1,327 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{Chr, EmuError, Mapper, Mirroring, Rom, decode_state, encode_state};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// CPU cycles per step of the 5B's tone counters
+const TONE_PRESCALER: u8 = 16;
+/// Brings the 5B's three channels in line with the APU, which they're
+/// about as loud as on a Famicom
+const OUTPUT_GAIN: f32 = 0.06;
+
+/// The 5B's square channels on an AY-3-8910, a subset of its registers
+/// https://www.nesdev.org/wiki/Sunsoft_5B_audio
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Sunsoft5b {
+    register: u8,
+    /// Tone periods, 12 bits each
+    periods: [u16; 3],
+    /// Register 7, active low: bits 0-2 turn off the tones
+    mixer: u8,
+    volumes: [u8; 3],
+    counters: [u16; 3],
+    outputs: [bool; 3],
+    prescaler: u8,
+}
+
+impl Sunsoft5b {
+    fn write(&mut self, data: u8) {
+        match self.register {
+            register @ 0..=5 => {
+                let channel = register as usize / 2;
+                self.periods[channel] = if register % 2 == 0 {
+                    self.periods[channel] & 0x0F00 | data as u16
+                } else {
+                    self.periods[channel] & 0x00FF | ((data & 0x0F) as u16) << 8
+                };
+            }
+            0x07 => self.mixer = data,
+            register @ 0x08..=0x0A => self.volumes[register as usize - 8] = data & 0x0F,
+            // Noise, the envelope and the I/O ports, which no released game uses
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < TONE_PRESCALER {
+            return;
+        }
+        self.prescaler = 0;
+        for channel in 0..3 {
+            self.counters[channel] += 1;
+            if self.counters[channel] >= self.periods[channel].max(1) {
+                self.counters[channel] = 0;
+                self.outputs[channel] = !self.outputs[channel];
+            }
+        }
+    }
+
+    /// Volumes step by 3dB, so 15 is full scale and each step below is
+    /// about 0.7 of the one above
+    fn output(&self) -> f32 {
+        (0..3)
+            .filter(|&channel| self.mixer & 1 << channel == 0 && self.outputs[channel])
+            .map(|channel| match self.volumes[channel] {
+                0 => 0.0,
+                volume => 10f32.powf(-3.0 * (15 - volume) as f32 / 20.0),
+            })
+            .sum::<f32>()
+            * OUTPUT_GAIN
+    }
+}
+
+/// Mapper 69: Sunsoft FME-7 with 8KB PRG banks, 1KB CHR banks, a CPU cycle
+/// IRQ counter and, on the 5B variant, three extra square channels.
+/// https://www.nesdev.org/wiki/Sunsoft_FME-7
+#[derive(Serialize, Deserialize)]
+pub struct Fme7 {
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    /// Which register the next parameter write goes to
+    command: u8,
+    chr_banks: [u8; 8],
+    /// Command 8: ROM bank or RAM at `$6000`
+    prg_bank_6000: u8,
+    prg_banks: [u8; 3],
+    mirroring: Mirroring,
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+    audio: Sunsoft5b,
+}
+
+impl Fme7 {
+    /// An empty `chr_rom` means the cartridge has CHR RAM instead
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Result<Self, EmuError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(EmuError::InvalidRom(format!(
+                "FME-7 PRG ROM must be a multiple of 8KB, got {} bytes",
+                prg_rom.len()
+            )));
+        }
+        Ok(Self {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr: Chr::new(chr_rom, CHR_RAM_SIZE),
+            command: 0,
+            chr_banks: [0; 8],
+            prg_bank_6000: 0,
+            prg_banks: [0; 3],
+            mirroring,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5b::default(),
+        })
+    }
+
+    pub fn from_rom(rom: &Rom) -> Result<Self, EmuError> {
+        Self::new(rom.prg_rom.clone(), rom.chr_rom.clone(), rom.mirroring)
+    }
+
+    fn prg_rom_read(&self, bank: u8, addr: u16) -> u8 {
+        let bank = bank as usize % (self.prg_rom.len() / PRG_BANK_SIZE);
+        self.prg_rom[bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE]
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE] as usize;
+        bank % (self.chr.len() / CHR_BANK_SIZE) * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            command @ 0x0..=0x7 => self.chr_banks[command as usize] = data,
+            0x8 => self.prg_bank_6000 = data,
+            command @ 0x9..=0xB => self.prg_banks[command as usize - 9] = data & 0x3F,
+            0xC => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0xD => {
+                self.irq_enabled = data & 0x01 != 0;
+                self.irq_counter_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = self.irq_counter & 0xFF00 | data as u16,
+            _ => self.irq_counter = self.irq_counter & 0x00FF | (data as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => {
+                let ram = self.prg_bank_6000 & 0x40 != 0;
+                let ram_enabled = self.prg_bank_6000 & 0x80 != 0;
+                match (ram, ram_enabled) {
+                    (false, _) => self.prg_rom_read(self.prg_bank_6000 & 0x3F, addr),
+                    (true, true) => self.prg_ram[(addr - 0x6000) as usize],
+                    // Open bus
+                    (true, false) => 0,
+                }
+            }
+            0x8000..=0xDFFF => {
+                let slot = (addr - 0x8000) as usize / PRG_BANK_SIZE;
+                self.prg_rom_read(self.prg_banks[slot], addr)
+            }
+            0xE000..=0xFFFF => {
+                let last = (self.prg_rom.len() / PRG_BANK_SIZE - 1) as u8;
+                self.prg_rom_read(last, addr)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_bank_6000 & 0xC0 == 0xC0 => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            0x8000..=0x9FFF => self.command = data & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(data),
+            0xC000..=0xDFFF => self.audio.register = data & 0x0F,
+            0xE000..=0xFFFF => self.audio.write(data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr.read(self.chr_index(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.chr.write(index, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let saved: Self = decode_state(state)?;
+        let chr = self.chr.restore(saved.chr)?;
+        *self = Self {
+            prg_rom: std::mem::take(&mut self.prg_rom),
+            chr,
+            ..saved
+        };
+        Ok(())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn tick(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.tick();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fme7() -> Fme7 {
+        let prg_rom = (0..8).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect();
+        Fme7::new(prg_rom, vec![], Mirroring::Vertical).unwrap()
+    }
+
+    fn command(fme7: &mut Fme7, command: u8, parameter: u8) {
+        fme7.cpu_write(0x8000, command);
+        fme7.cpu_write(0xA000, parameter);
+    }
+
+    #[test]
+    fn test_prg_banks_and_ram() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 0x9, 3);
+        command(&mut fme7, 0xB, 5);
+        command(&mut fme7, 0x8, 2);
+        let banks =
+            |fme7: &Fme7| [0x6000, 0x8000, 0xA000, 0xC000, 0xE000].map(|a| fme7.cpu_peek(a));
+        assert_eq!(banks(&fme7), [2, 3, 0, 5, 7]);
+
+        // RAM selected and enabled
+        command(&mut fme7, 0x8, 0xC0);
+        fme7.cpu_write(0x6000, 0x42);
+        assert_eq!(fme7.cpu_peek(0x6000), 0x42);
+
+        command(&mut fme7, 0xC, 3);
+        assert_eq!(fme7.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn test_irq_counter() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 0xE, 0x01);
+        command(&mut fme7, 0xF, 0x00);
+        command(&mut fme7, 0xD, 0x81);
+        fme7.tick();
+        assert!(!fme7.irq());
+        fme7.tick();
+        assert!(fme7.irq());
+
+        // Writing the control register acknowledges
+        command(&mut fme7, 0xD, 0x00);
+        assert!(!fme7.irq());
+    }
+
+    #[test]
+    fn test_5b_square() {
+        let mut fme7 = fme7();
+        let write = |fme7: &mut Fme7, register, data| {
+            fme7.cpu_write(0xC000, register);
+            fme7.cpu_write(0xE000, data);
+        };
+        // Channel A at period 2, full volume, only its tone enabled
+        write(&mut fme7, 0x0, 2);
+        write(&mut fme7, 0x8, 15);
+        write(&mut fme7, 0x7, 0b11_1110);
+
+        let mut levels = vec![];
+        for _ in 0..4 * 2 * TONE_PRESCALER {
+            fme7.tick();
+            levels.push(fme7.audio_output());
+        }
+        levels.dedup();
+        assert_eq!(levels, [0.0, OUTPUT_GAIN, 0.0, OUTPUT_GAIN, 0.0]);
+    }
+}
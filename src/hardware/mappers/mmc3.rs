@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{Chr, EmuError, Mapper, Mirroring, Rom, decode_state, encode_state};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// Mapper 4: 8KB PRG banks, 1KB/2KB CHR banks, switchable mirroring and a
+/// scanline counter that raises an IRQ, used for status bars and raster
+/// effects. https://www.nesdev.org/wiki/MMC3
+#[derive(Serialize, Deserialize)]
+pub struct Mmc3 {
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    /// Which of `registers` the next bank data write goes to, plus the PRG
+    /// and CHR layout bits
+    bank_select: u8,
+    /// R0-R5 select CHR banks, R6 and R7 PRG banks
+    registers: [u8; 8],
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl Mmc3 {
+    /// An empty `chr_rom` means the cartridge has CHR RAM instead
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Result<Self, EmuError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(EmuError::InvalidRom(format!(
+                "MMC3 PRG ROM must be a multiple of 8KB, got {} bytes",
+                prg_rom.len()
+            )));
+        }
+        Ok(Self {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr: Chr::new(chr_rom, CHR_RAM_SIZE),
+            bank_select: 0,
+            registers: [0; 8],
+            mirroring,
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        })
+    }
+
+    pub fn from_rom(rom: &Rom) -> Result<Self, EmuError> {
+        Self::new(rom.prg_rom.clone(), rom.chr_rom.clone(), rom.mirroring)
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        let second_last = banks.saturating_sub(2);
+        let swapped = self.bank_select & 0x40 != 0;
+        let bank = match ((addr - 0x8000) as usize / PRG_BANK_SIZE, swapped) {
+            (0, false) | (2, true) => self.registers[6] as usize & 0x3F,
+            (0, true) | (2, false) => second_last,
+            (1, _) => self.registers[7] as usize & 0x3F,
+            _ => banks - 1,
+        };
+        bank % banks
+    }
+
+    fn chr_bank(&self, addr: u16) -> usize {
+        // CHR A12 inversion swaps the 2KB and 1KB halves
+        let addr = if self.bank_select & 0x80 != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+        let bank = match addr as usize / CHR_BANK_SIZE {
+            0 => self.registers[0] & 0xFE,
+            1 => self.registers[0] | 0x01,
+            2 => self.registers[1] & 0xFE,
+            3 => self.registers[1] | 0x01,
+            slot => self.registers[slot - 2],
+        };
+        bank as usize % (self.chr.len() / CHR_BANK_SIZE)
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                self.prg_rom[self.prg_bank(addr) * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        let even = addr & 1 == 0;
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram_write_protect => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            0x8000..=0x9FFF if even => self.bank_select = data,
+            0x8000..=0x9FFF => self.registers[(self.bank_select & 0x07) as usize] = data,
+            // Four-screen boards ignore the mirroring register
+            0xA000..=0xBFFF if even && self.mirroring != Mirroring::FourScreen => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0xA000..=0xBFFF if even => {}
+            0xA000..=0xBFFF => {
+                self.prg_ram_enabled = data & 0x80 != 0;
+                self.prg_ram_write_protect = data & 0x40 != 0;
+            }
+            0xC000..=0xDFFF if even => self.irq_latch = data,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr
+            .read(self.chr_bank(addr) * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let index = self.chr_bank(addr) * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE;
+        self.chr.write(index, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let saved: Self = decode_state(state)?;
+        let chr = self.chr.restore(saved.chr)?;
+        *self = Self {
+            prg_rom: std::mem::take(&mut self.prg_rom),
+            chr,
+            ..saved
+        };
+        Ok(())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn clock_scanline(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// 8 PRG banks and 8 CHR banks, each filled with its own number
+    fn mmc3() -> Mmc3 {
+        let prg_rom = (0..8).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect();
+        let chr_rom = (0..8).flat_map(|bank| [bank; CHR_BANK_SIZE]).collect();
+        Mmc3::new(prg_rom, chr_rom, Mirroring::Vertical).unwrap()
+    }
+
+    #[test]
+    fn test_bank_switching() {
+        let mut mmc3 = mmc3();
+        // R6 = 3, R7 = 5
+        mmc3.cpu_write(0x8000, 6);
+        mmc3.cpu_write(0x8001, 3);
+        mmc3.cpu_write(0x8000, 7);
+        mmc3.cpu_write(0x8001, 5);
+        let banks = |mmc3: &Mmc3| [0x8000, 0xA000, 0xC000, 0xE000].map(|a| mmc3.cpu_peek(a));
+        assert_eq!(banks(&mmc3), [3, 5, 6, 7]);
+
+        // PRG mode 1 swaps $8000 and $C000, CHR inversion swaps the halves
+        mmc3.cpu_write(0x8000, 0xC2);
+        mmc3.cpu_write(0x8001, 4);
+        assert_eq!(banks(&mmc3), [6, 5, 3, 7]);
+        assert_eq!(mmc3.ppu_read(0x0000), 4);
+        assert_eq!(mmc3.ppu_read(0x1000), 0);
+
+        mmc3.cpu_write(0xA000, 1);
+        assert_eq!(mmc3.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_scanline_irq() {
+        let mut mmc3 = mmc3();
+        mmc3.cpu_write(0xC000, 2);
+        mmc3.cpu_write(0xC001, 0);
+        mmc3.cpu_write(0xE001, 0);
+
+        // Reloads to 2, then counts down to 0
+        mmc3.clock_scanline();
+        mmc3.clock_scanline();
+        assert!(!mmc3.irq());
+        mmc3.clock_scanline();
+        assert!(mmc3.irq());
+
+        // Writing $E000 acknowledges and disables
+        mmc3.cpu_write(0xE000, 0);
+        assert!(!mmc3.irq());
+        for _ in 0..4 {
+            mmc3.clock_scanline();
+        }
+        assert!(!mmc3.irq());
+    }
+}
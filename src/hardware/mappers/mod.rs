@@ -0,0 +1,271 @@
+use std::{cell::RefCell, rc::Rc};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
+
+use crate::hardware::{EmuError, Mirroring, Rom, Rtc};
+
+mod camerica;
+mod cnrom;
+mod color_dreams;
+mod fme7;
+mod mmc3;
+mod namco163;
+mod nrom;
+mod uxrom;
+mod vrc;
+pub use camerica::*;
+pub use cnrom::*;
+pub use color_dreams::*;
+pub use fme7::*;
+pub use mmc3::*;
+pub use namco163::*;
+pub use nrom::*;
+pub use uxrom::*;
+pub use vrc::*;
+
+/// The cartridge hardware between the console and the ROM chips, which
+/// decides what the CPU and PPU see at each address and how the nametables
+/// are mirrored. https://www.nesdev.org/wiki/Mapper
+pub trait Mapper {
+    /// What a CPU read of the cartridge space, `$4020..=$FFFF`, would
+    /// return, without side effects, for debuggers and tracing
+    fn cpu_peek(&self, addr: u16) -> u8;
+
+    /// A CPU read of the cartridge space, for boards whose registers react
+    /// to being read
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.cpu_peek(addr)
+    }
+
+    /// A CPU write to the cartridge space, usually a bank switch
+    fn cpu_write(&mut self, addr: u16, data: u8);
+
+    /// A PPU read of the pattern tables, `$0000..=$1FFF`
+    fn ppu_read(&self, addr: u16) -> u8;
+
+    /// A PPU write to the pattern tables, ignored unless they're CHR RAM
+    fn ppu_write(&mut self, addr: u16, data: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    /// Registers and RAM for a savestate, see [`encode_state`]
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Restores what [`Mapper::save_state`] returned. The cartridge must
+    /// be the same game.
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError>;
+
+    /// The PRG RAM at `$6000..=$7FFF`, which a battery keeps powered on
+    /// cartridges that have one
+    fn prg_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    /// The cartridge's real-time clock, for the few boards that have one
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+
+    /// The PPU's A12 address line rising while rendering, which happens
+    /// once per scanline when backgrounds and sprites use different pattern
+    /// tables. Scanline counters like the MMC3's count these.
+    fn clock_scanline(&mut self) {}
+
+    /// Whether the cartridge is asserting the CPU's IRQ line
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Advances one CPU cycle, for boards with cycle counters or sound
+    fn tick(&mut self) {}
+
+    /// The current level of the cartridge's expansion audio, mixed in with
+    /// the APU's channels. On the same scale as the APU's `0.0..=1.0`.
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+}
+
+/// Serializes a mapper for [`Mapper::save_state`]. Mappers derive serde
+/// with their ROM skipped, and put their pattern tables in a [`Chr`] so
+/// only CHR RAM ends up in the state.
+pub(crate) fn encode_state<T: Serialize>(mapper: &T) -> Vec<u8> {
+    bincode::serialize(mapper).expect("mapper state always serializes")
+}
+
+pub(crate) fn decode_state<T: DeserializeOwned>(state: &[u8]) -> Result<T, EmuError> {
+    bincode::deserialize(state)
+        .map_err(|err| EmuError::InvalidSaveState(format!("mapper state: {err}")))
+}
+
+/// Pattern table memory: CHR ROM, or CHR RAM on cartridges without any
+#[derive(Debug, Default)]
+pub(crate) struct Chr {
+    data: Vec<u8>,
+    ram: bool,
+}
+
+impl Chr {
+    /// An empty `rom` means `ram_size` bytes of CHR RAM instead
+    pub(crate) fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        if rom.is_empty() {
+            Self {
+                data: vec![0; ram_size],
+                ram: true,
+            }
+        } else {
+            Self {
+                data: rom,
+                ram: false,
+            }
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub(crate) fn read(&self, index: usize) -> u8 {
+        self.data[index]
+    }
+
+    /// Ignored for CHR ROM
+    pub(crate) fn write(&mut self, index: usize, data: u8) {
+        if self.ram {
+            self.data[index] = data;
+        }
+    }
+
+    /// What to keep after loading `saved` from a state: the saved contents
+    /// for CHR RAM, the ROM otherwise
+    pub(crate) fn restore(&mut self, saved: Chr) -> Result<Chr, EmuError> {
+        if !self.ram {
+            return Ok(std::mem::take(self));
+        }
+        if saved.data.len() != self.data.len() {
+            return Err(EmuError::InvalidSaveState(format!(
+                "expected {} bytes of CHR RAM, got {}",
+                self.data.len(),
+                saved.data.len()
+            )));
+        }
+        Ok(saved)
+    }
+}
+
+impl Serialize for Chr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.ram.then_some(&self.data).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Chr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = Option::<Vec<u8>>::deserialize(deserializer)?;
+        Ok(Self {
+            ram: data.is_some(),
+            data: data.unwrap_or_default(),
+        })
+    }
+}
+
+/// A cartridge shared between the CPU bus and the PPU, which both talk to it
+pub type Cartridge = Rc<RefCell<dyn Mapper>>;
+
+type Constructor = fn(&Rom) -> Result<Cartridge, EmuError>;
+
+/// Supported mappers by iNES mapper number
+const MAPPERS: &[(u8, &str, Constructor)] = &[
+    (0, "NROM", |rom| {
+        Ok(Rc::new(RefCell::new(Nrom::from_rom(rom)?)))
+    }),
+    (2, "UxROM", |rom| {
+        Ok(Rc::new(RefCell::new(Uxrom::from_rom(rom)?)))
+    }),
+    (3, "CNROM", |rom| {
+        Ok(Rc::new(RefCell::new(Cnrom::from_rom(rom)?)))
+    }),
+    (4, "MMC3", |rom| {
+        Ok(Rc::new(RefCell::new(Mmc3::from_rom(rom)?)))
+    }),
+    (11, "Color Dreams", |rom| {
+        Ok(Rc::new(RefCell::new(ColorDreams::from_rom(rom)?)))
+    }),
+    (19, "Namco 163", |rom| {
+        Ok(Rc::new(RefCell::new(Namco163::from_rom(rom)?)))
+    }),
+    (21, "VRC4", |rom| {
+        Ok(Rc::new(RefCell::new(Vrc::from_rom(rom)?)))
+    }),
+    (22, "VRC2", |rom| {
+        Ok(Rc::new(RefCell::new(Vrc::from_rom(rom)?)))
+    }),
+    (23, "VRC2/VRC4", |rom| {
+        Ok(Rc::new(RefCell::new(Vrc::from_rom(rom)?)))
+    }),
+    (25, "VRC2/VRC4", |rom| {
+        Ok(Rc::new(RefCell::new(Vrc::from_rom(rom)?)))
+    }),
+    (69, "FME-7", |rom| {
+        Ok(Rc::new(RefCell::new(Fme7::from_rom(rom)?)))
+    }),
+    (71, "Camerica", |rom| {
+        Ok(Rc::new(RefCell::new(Camerica::from_rom(rom)?)))
+    }),
+];
+
+/// Builds the mapper `rom` asks for
+pub fn create_mapper(rom: &Rom) -> Result<Cartridge, EmuError> {
+    let (_, _, constructor) = MAPPERS
+        .iter()
+        .find(|(number, _, _)| *number == rom.mapper)
+        .ok_or(EmuError::UnsupportedMapper(rom.mapper))?;
+    constructor(rom)
+}
+
+/// Name of a supported mapper, e.g. for showing in a ROM info dialog
+pub fn mapper_name(number: u8) -> Option<&'static str> {
+    MAPPERS
+        .iter()
+        .find(|(mapper, _, _)| *mapper == number)
+        .map(|(_, name, _)| *name)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::rom::test::ines;
+
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let rom = Rom::new(&ines(1, 1, 0x01, 0)).unwrap();
+        let cartridge = create_mapper(&rom).unwrap();
+        assert_eq!(cartridge.borrow().mirroring(), Mirroring::Vertical);
+        assert_eq!(mapper_name(0), Some("NROM"));
+
+        let rom = Rom::new(&ines(2, 1, 0x30, 0x10)).unwrap();
+        assert!(create_mapper(&rom).is_ok());
+        assert_eq!(mapper_name(19), Some("Namco 163"));
+
+        let rom = Rom::new(&ines(1, 1, 0xF0, 0xF0)).unwrap();
+        assert_eq!(
+            create_mapper(&rom).err(),
+            Some(EmuError::UnsupportedMapper(0xFF))
+        );
+    }
+
+    #[test]
+    fn test_unlicensed_mappers() {
+        // Mapper numbers are split across the high nibbles of flags 6 and 7
+        for (flags6, flags7, name) in [(0xB0, 0x00, "Color Dreams"), (0x70, 0x40, "Camerica")] {
+            let rom = Rom::new(&ines(2, 1, flags6, flags7)).unwrap();
+            assert!(create_mapper(&rom).is_ok());
+            assert_eq!(mapper_name(rom.mapper), Some(name));
+        }
+    }
+}
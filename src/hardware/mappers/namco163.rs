@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{Chr, EmuError, Mapper, Mirroring, Rom, decode_state, encode_state};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+const SOUND_RAM_SIZE: usize = 0x80;
+
+/// CPU cycles the sound chip spends on each channel before moving on
+const CYCLES_PER_CHANNEL: u8 = 15;
+/// Brings the chip's output in line with the APU's. Cartridges differ a lot
+/// here, this is roughly the loudness of the later boards.
+const OUTPUT_GAIN: f32 = 1.0 / 600.0;
+
+/// Mapper 19: Namco 163 with 8KB PRG banks, 1KB CHR banks, a 15-bit
+/// CPU cycle IRQ counter and up to eight wavetable channels playing 4-bit
+/// samples from 128 bytes of internal RAM.
+/// https://www.nesdev.org/wiki/INES_Mapper_019
+///
+/// Nametables are limited to the layouts [`Mirroring`] can express, so
+/// games that page CHR ROM into the nametables show the console's VRAM
+/// instead.
+#[derive(Serialize, Deserialize)]
+pub struct Namco163 {
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    /// Console VRAM page for each of the four nametables
+    nametables: [u8; 4],
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    /// Wavetables and channel registers, shared with the CPU through
+    /// `$4800` and `$F800`
+    sound_ram: Vec<u8>,
+    /// `$F800`: RAM address, auto-incremented on access if bit 7 is set
+    sound_addr: u8,
+    sound_enabled: bool,
+    channel: u8,
+    channel_cycles: u8,
+    /// Last output of each channel, -120..=105
+    channel_outputs: [i16; 8],
+}
+
+impl Namco163 {
+    /// An empty `chr_rom` means the cartridge has CHR RAM instead
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Result<Self, EmuError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(EmuError::InvalidRom(format!(
+                "Namco 163 PRG ROM must be a multiple of 8KB, got {} bytes",
+                prg_rom.len()
+            )));
+        }
+        Ok(Self {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr: Chr::new(chr_rom, CHR_RAM_SIZE),
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametables: [0, 1, 0, 1],
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            sound_ram: vec![0; SOUND_RAM_SIZE],
+            sound_addr: 0,
+            sound_enabled: true,
+            channel: 0,
+            channel_cycles: 0,
+            channel_outputs: [0; 8],
+        })
+    }
+
+    pub fn from_rom(rom: &Rom) -> Result<Self, EmuError> {
+        Self::new(rom.prg_rom.clone(), rom.chr_rom.clone())
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE] as usize;
+        bank % (self.chr.len() / CHR_BANK_SIZE) * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE
+    }
+
+    /// Index into `sound_ram` for the next `$4800` access
+    fn next_sound_addr(&mut self) -> usize {
+        let addr = self.sound_addr;
+        if addr & 0x80 != 0 {
+            self.sound_addr = 0x80 | (addr.wrapping_add(1) & 0x7F);
+        }
+        (addr & 0x7F) as usize
+    }
+
+    /// Enabled channels, from 1 to 8. Channel 0's registers are at the top
+    /// of sound RAM and each further channel sits 8 bytes below.
+    fn channel_count(&self) -> u8 {
+        (self.sound_ram[0x7F] >> 4 & 0x07) + 1
+    }
+
+    /// Advances the current channel's phase and plays its next sample
+    fn clock_channel(&mut self) {
+        let base = 0x78 - 8 * self.channel as usize;
+        let regs = &self.sound_ram[base..base + 8];
+        let frequency = u32::from_le_bytes([regs[0], regs[2], regs[4] & 0x03, 0]);
+        let length = 256 - (regs[4] & 0xFC) as u32;
+        let mut phase = u32::from_le_bytes([regs[1], regs[3], regs[5], 0]);
+        let wave_addr = regs[6] as u32;
+        let volume = (regs[7] & 0x0F) as i16;
+
+        phase = (phase + frequency) % (length << 16);
+        let sample_addr = ((phase >> 16) + wave_addr) & 0xFF;
+        let byte = self.sound_ram[(sample_addr / 2) as usize];
+        let sample = if sample_addr.is_multiple_of(2) {
+            byte & 0x0F
+        } else {
+            byte >> 4
+        };
+        self.channel_outputs[self.channel as usize] = (sample as i16 - 8) * volume;
+
+        let [phase_low, phase_mid, phase_high, _] = phase.to_le_bytes();
+        self.sound_ram[base + 1] = phase_low;
+        self.sound_ram[base + 3] = phase_mid;
+        self.sound_ram[base + 5] = phase_high;
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.sound_ram[(self.sound_addr & 0x7F) as usize],
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7,
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+                let bank = match (addr - 0x8000) as usize / PRG_BANK_SIZE {
+                    slot @ 0..=2 => self.prg_banks[slot] as usize % banks,
+                    _ => banks - 1,
+                };
+                self.prg_rom[bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => {
+                let index = self.next_sound_addr();
+                self.sound_ram[index]
+            }
+            _ => self.cpu_peek(addr),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4800..=0x4FFF => {
+                let index = self.next_sound_addr();
+                self.sound_ram[index] = data;
+            }
+            0x5000..=0x57FF => {
+                self.irq_counter = self.irq_counter & 0x7F00 | data as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = self.irq_counter & 0x00FF | ((data & 0x7F) as u16) << 8;
+                self.irq_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = data,
+            0x8000..=0xBFFF => self.chr_banks[(addr - 0x8000) as usize / 0x800] = data,
+            0xC000..=0xDFFF => {
+                // Values below $E0 would page in CHR ROM, see the type docs
+                self.nametables[(addr - 0xC000) as usize / 0x800] = data & 0x01;
+            }
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = data & 0x3F;
+                self.sound_enabled = data & 0x40 == 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = data & 0x3F,
+            0xF000..=0xF7FF => self.prg_banks[2] = data & 0x3F,
+            0xF800..=0xFFFF => self.sound_addr = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr.read(self.chr_index(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.chr.write(index, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.nametables {
+            [0, 0, 1, 1] => Mirroring::Horizontal,
+            [0, 0, 0, 0] => Mirroring::SingleScreenLower,
+            [1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+            _ => Mirroring::Vertical,
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let saved: Self = decode_state(state)?;
+        let chr = self.chr.restore(saved.chr)?;
+        *self = Self {
+            prg_rom: std::mem::take(&mut self.prg_rom),
+            chr,
+            ..saved
+        };
+        Ok(())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn tick(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+
+        if !self.sound_enabled {
+            return;
+        }
+        self.channel_cycles += 1;
+        if self.channel_cycles == CYCLES_PER_CHANNEL {
+            self.channel_cycles = 0;
+            self.clock_channel();
+            self.channel = (self.channel + 1) % self.channel_count();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// The chip plays one channel at a time; averaging them is what the
+    /// cartridge's output filter ends up doing
+    fn audio_output(&self) -> f32 {
+        if !self.sound_enabled {
+            return 0.0;
+        }
+        let channels = self.channel_count() as usize;
+        let sum: i16 = self.channel_outputs[..channels].iter().sum();
+        sum as f32 / channels as f32 * OUTPUT_GAIN
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn namco163() -> Namco163 {
+        let prg_rom = (0..8).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect();
+        Namco163::new(prg_rom, vec![]).unwrap()
+    }
+
+    #[test]
+    fn test_prg_banks_and_sound_ram() {
+        let mut n163 = namco163();
+        n163.cpu_write(0xE000, 2);
+        n163.cpu_write(0xF000, 5);
+        let banks = |n163: &Namco163| [0x8000, 0xA000, 0xC000, 0xE000].map(|a| n163.cpu_peek(a));
+        assert_eq!(banks(&n163), [2, 0, 5, 7]);
+
+        // Auto-incrementing writes, then reads from the start
+        n163.cpu_write(0xF800, 0x80 | 0x7E);
+        n163.cpu_write(0x4800, 0x11);
+        n163.cpu_write(0x4800, 0x22);
+        n163.cpu_write(0xF800, 0x7F);
+        assert_eq!(n163.cpu_read(0x4800), 0x22);
+        assert_eq!(n163.cpu_read(0x4800), 0x22);
+        assert_eq!(n163.sound_ram[0x00], 0x00);
+
+        // Peeking leaves the address where it is
+        n163.cpu_write(0xF800, 0x80 | 0x7E);
+        assert_eq!(n163.cpu_peek(0x4800), 0x11);
+        assert_eq!(n163.cpu_peek(0x4800), 0x11);
+        assert_eq!(n163.cpu_read(0x4800), 0x11);
+        assert_eq!(n163.cpu_read(0x4800), 0x22);
+    }
+
+    #[test]
+    fn test_irq_counter() {
+        let mut n163 = namco163();
+        n163.cpu_write(0x5000, 0xFD);
+        n163.cpu_write(0x5800, 0xFF);
+        n163.tick();
+        assert!(!n163.irq());
+        n163.tick();
+        assert!(n163.irq());
+        assert_eq!(n163.cpu_read(0x5800), 0xFF);
+
+        n163.cpu_write(0x5800, 0x00);
+        assert!(!n163.irq());
+    }
+
+    #[test]
+    fn test_wavetable_channel() {
+        let mut n163 = namco163();
+        // A square wave: four samples at 15, four at 0
+        n163.sound_ram[0..4].copy_from_slice(&[0xFF, 0xFF, 0x00, 0x00]);
+        // Channel 0 alone: one sample step per update, 8 sample wave at
+        // address 0, full volume
+        n163.sound_ram[0x78..0x80].copy_from_slice(&[0x00, 0, 0x00, 0, 0xF9, 0, 0, 0x0F]);
+
+        let mut outputs = vec![];
+        for _ in 0..8 * CYCLES_PER_CHANNEL {
+            n163.tick();
+            outputs.push(n163.channel_outputs[0]);
+        }
+        outputs.dedup();
+        assert_eq!(outputs, [0, 7 * 15, -8 * 15, 7 * 15]);
+        assert!(n163.audio_output() > 0.0);
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{Chr, EmuError, Mapper, Mirroring, Rom, decode_state, encode_state};
+
+const PRG_ROM_BANK_SIZE: usize = 0x4000;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_SIZE: usize = 0x2000;
+
+/// Mapper 0: no bank switching, 16KB or 32KB PRG ROM and 8KB of CHR ROM or
+/// RAM. 16KB ROMs are mirrored into both halves of `$8000..=$FFFF`.
+/// https://www.nesdev.org/wiki/NROM
+#[derive(Serialize, Deserialize)]
+pub struct Nrom {
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    /// Family BASIC's work RAM at `$6000..=$7FFF`, harmless for others
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+}
+
+impl Default for Nrom {
+    /// A blank 32KB cartridge with CHR RAM
+    fn default() -> Self {
+        Self::new(
+            vec![0; 2 * PRG_ROM_BANK_SIZE],
+            vec![],
+            Mirroring::Horizontal,
+        )
+        .expect("blank cartridge is valid")
+    }
+}
+
+impl Nrom {
+    /// An empty `chr_rom` means the cartridge has CHR RAM instead
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Result<Self, EmuError> {
+        if prg_rom.len() != PRG_ROM_BANK_SIZE && prg_rom.len() != 2 * PRG_ROM_BANK_SIZE {
+            return Err(EmuError::InvalidRom(format!(
+                "NROM PRG ROM must be 16KB or 32KB, got {} bytes",
+                prg_rom.len()
+            )));
+        }
+        Ok(Self {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr: Chr::new(chr_rom, CHR_SIZE),
+            mirroring,
+        })
+    }
+
+    pub fn from_rom(rom: &Rom) -> Result<Self, EmuError> {
+        Self::new(rom.prg_rom.clone(), rom.chr_rom.clone(), rom.mirroring)
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => self.prg_rom[(addr - 0x8000) as usize % self.prg_rom.len()],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[(addr - 0x6000) as usize] = data;
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr.read(addr as usize % self.chr.len())
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize % CHR_SIZE, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let saved: Self = decode_state(state)?;
+        let chr = self.chr.restore(saved.chr)?;
+        *self = Self {
+            prg_rom: std::mem::take(&mut self.prg_rom),
+            chr,
+            ..saved
+        };
+        Ok(())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_16kb_prg_is_mirrored() {
+        let mut prg_rom = vec![0; PRG_ROM_BANK_SIZE];
+        prg_rom[0x3FFC] = 0x34;
+        let mut nrom = Nrom::new(prg_rom, vec![0xAA; CHR_SIZE], Mirroring::Vertical).unwrap();
+        assert_eq!(nrom.cpu_peek(0xBFFC), 0x34);
+        assert_eq!(nrom.cpu_peek(0xFFFC), 0x34);
+
+        // CHR ROM can't be written, PRG RAM can
+        nrom.ppu_write(0x0010, 0x55);
+        assert_eq!(nrom.ppu_read(0x0010), 0xAA);
+        nrom.cpu_write(0x6000, 0x55);
+        assert_eq!(nrom.cpu_peek(0x6000), 0x55);
+
+        assert!(Nrom::new(vec![0; 0x1000], vec![], Mirroring::Vertical).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{Chr, EmuError, Mapper, Mirroring, Rom, decode_state, encode_state};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_SIZE: usize = 0x2000;
+
+/// Mapper 2: a switchable 16KB bank at `$8000`, the last bank fixed at
+/// `$C000` and 8KB of CHR RAM. https://www.nesdev.org/wiki/UxROM
+#[derive(Serialize, Deserialize)]
+pub struct Uxrom {
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Uxrom {
+    /// An empty `chr_rom` means the cartridge has CHR RAM, as nearly all do
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Result<Self, EmuError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(EmuError::InvalidRom(format!(
+                "UxROM PRG ROM must be a multiple of 16KB, got {} bytes",
+                prg_rom.len()
+            )));
+        }
+        Ok(Self {
+            prg_rom,
+            chr: Chr::new(chr_rom, CHR_SIZE),
+            prg_bank: 0,
+            mirroring,
+        })
+    }
+
+    pub fn from_rom(rom: &Rom) -> Result<Self, EmuError> {
+        Self::new(rom.prg_rom.clone(), rom.chr_rom.clone(), rom.mirroring)
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank % banks,
+            0xC000..=0xFFFF => banks - 1,
+            _ => return 0,
+        };
+        self.prg_rom[bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE]
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x8000..=0xFFFF = addr {
+            // Bus conflict: the ROM drives the data bus too, so the written
+            // value gets ANDed with the byte at that address
+            self.prg_bank = (data & self.cpu_peek(addr)) as usize;
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr.read(addr as usize % CHR_SIZE)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize % CHR_SIZE, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let saved: Self = decode_state(state)?;
+        let chr = self.chr.restore(saved.chr)?;
+        *self = Self {
+            prg_rom: std::mem::take(&mut self.prg_rom),
+            chr,
+            ..saved
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prg_banks() {
+        let prg_rom = (0..8).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect();
+        let mut uxrom = Uxrom::new(prg_rom, vec![], Mirroring::Vertical).unwrap();
+
+        // The fixed bank holds 7, so nothing is masked off
+        uxrom.cpu_write(0xC000, 5);
+        assert_eq!(uxrom.cpu_peek(0x8000), 5);
+        assert_eq!(uxrom.cpu_peek(0xFFFF), 7);
+
+        // Bank 5 is under $8000 now and masks out bit 1
+        uxrom.cpu_write(0x8000, 6);
+        assert_eq!(uxrom.cpu_peek(0x8000), 4);
+
+        uxrom.ppu_write(0x0123, 0x55);
+        assert_eq!(uxrom.ppu_read(0x0123), 0x55);
+    }
+}
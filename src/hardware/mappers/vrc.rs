@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{Chr, EmuError, Mapper, Mirroring, Rom, decode_state, encode_state};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+/// PPU dots per scanline, the IRQ prescaler counts these three per cycle
+const DOTS_PER_SCANLINE: i16 = 341;
+
+/// How a board wires the CPU's address lines to the chip's register
+/// select pins A0 and A1. Each mapper number covers two boards that use
+/// different lines, but never the same ones, so both are decoded at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VrcBoard {
+    /// VRC4a with A0 on A1 and A1 on A2, VRC4c with A6 and A7
+    Mapper21,
+    /// VRC2a with A0 and A1 swapped. It ignores the lowest bit of CHR bank
+    /// numbers and has no IRQ.
+    Mapper22,
+    /// VRC2b and VRC4f on A0 and A1, VRC4e on A2 and A3
+    Mapper23,
+    /// VRC2c and VRC4b on A1 and A0, VRC4d on A3 and A2
+    Mapper25,
+}
+
+impl VrcBoard {
+    pub fn from_mapper(number: u8) -> Option<Self> {
+        match number {
+            21 => Some(VrcBoard::Mapper21),
+            22 => Some(VrcBoard::Mapper22),
+            23 => Some(VrcBoard::Mapper23),
+            25 => Some(VrcBoard::Mapper25),
+            _ => None,
+        }
+    }
+
+    /// CPU address bits that drive A0 and A1
+    fn select_lines(&self) -> (u16, u16) {
+        match self {
+            VrcBoard::Mapper21 => (0x0002 | 0x0040, 0x0004 | 0x0080),
+            VrcBoard::Mapper22 => (0x0002, 0x0001),
+            VrcBoard::Mapper23 => (0x0001 | 0x0004, 0x0002 | 0x0008),
+            VrcBoard::Mapper25 => (0x0002 | 0x0008, 0x0001 | 0x0004),
+        }
+    }
+
+    fn is_vrc2(&self) -> bool {
+        *self == VrcBoard::Mapper22
+    }
+}
+
+/// Mappers 21, 22, 23 and 25: Konami's VRC2 and VRC4 with two switchable
+/// 8KB PRG banks, eight 1KB CHR banks and, on the VRC4, an IRQ counter
+/// that counts scanlines or CPU cycles.
+/// https://www.nesdev.org/wiki/VRC2_and_VRC4
+#[derive(Serialize, Deserialize)]
+pub struct Vrc {
+    #[serde(skip)]
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    board: VrcBoard,
+    prg_banks: [u8; 2],
+    /// Swaps the first switchable bank with the fixed one at `$C000`
+    prg_swapped: bool,
+    /// Written a nibble at a time, 9 bits on the VRC4 and 8 on the VRC2
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    irq_latch: u8,
+    irq_counter: u8,
+    /// Counts down from 341 by 3 per CPU cycle to clock the counter once
+    /// per scanline
+    irq_prescaler: i16,
+    irq_enabled: bool,
+    /// Whether acknowledging re-enables the IRQ
+    irq_enabled_after_ack: bool,
+    /// Clocks the counter every CPU cycle instead of every scanline
+    irq_cycle_mode: bool,
+    irq_pending: bool,
+}
+
+impl Vrc {
+    /// An empty `chr_rom` means the cartridge has CHR RAM instead
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        mirroring: Mirroring,
+        board: VrcBoard,
+    ) -> Result<Self, EmuError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(EmuError::InvalidRom(format!(
+                "VRC PRG ROM must be a multiple of 8KB, got {} bytes",
+                prg_rom.len()
+            )));
+        }
+        Ok(Self {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr: Chr::new(chr_rom, CHR_RAM_SIZE),
+            board,
+            prg_banks: [0; 2],
+            prg_swapped: false,
+            chr_banks: [0; 8],
+            mirroring,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_prescaler: DOTS_PER_SCANLINE,
+            irq_enabled: false,
+            irq_enabled_after_ack: false,
+            irq_cycle_mode: false,
+            irq_pending: false,
+        })
+    }
+
+    pub fn from_rom(rom: &Rom) -> Result<Self, EmuError> {
+        let board =
+            VrcBoard::from_mapper(rom.mapper).ok_or(EmuError::UnsupportedMapper(rom.mapper))?;
+        Self::new(
+            rom.prg_rom.clone(),
+            rom.chr_rom.clone(),
+            rom.mirroring,
+            board,
+        )
+    }
+
+    /// `addr` with the board's select lines moved to bits 0 and 1, so
+    /// registers are `$8000..=$F003` on every board
+    fn register(&self, addr: u16) -> u16 {
+        let (a0, a1) = self.board.select_lines();
+        let a0 = (addr & a0 != 0) as u16;
+        let a1 = (addr & a1 != 0) as u16;
+        addr & 0xF000 | a1 << 1 | a0
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        let second_last = banks.saturating_sub(2);
+        let bank = match ((addr - 0x8000) as usize / PRG_BANK_SIZE, self.prg_swapped) {
+            (0, false) | (2, true) => self.prg_banks[0] as usize,
+            (0, true) | (2, false) => second_last,
+            (1, _) => self.prg_banks[1] as usize,
+            _ => banks - 1,
+        };
+        bank % banks
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE] as usize;
+        let bank = if self.board.is_vrc2() {
+            bank >> 1
+        } else {
+            bank
+        };
+        bank % (self.chr.len() / CHR_BANK_SIZE) * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0xFF {
+            self.irq_counter = self.irq_latch;
+            self.irq_pending = true;
+        } else {
+            self.irq_counter += 1;
+        }
+    }
+}
+
+impl Mapper for Vrc {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                self.prg_rom[self.prg_bank(addr) * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        let vrc2 = self.board.is_vrc2();
+        let register = self.register(addr);
+        match register {
+            // The VRC4's RAM enable bit at $9002 is ignored, RAM is always on
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = data,
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0x1F,
+            0x9000..=0x9003 if vrc2 => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0x9000 => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0x9002 => self.prg_swapped = data & 0x02 != 0,
+            0xA000..=0xA003 => self.prg_banks[1] = data & 0x1F,
+            0xB000..=0xEFFF => {
+                // Two registers per bank, the low nibble then the high bits
+                let bank =
+                    (register as usize - 0xB000) / 0x1000 * 2 + (register as usize & 0x02) / 2;
+                let data = data as u16;
+                self.chr_banks[bank] = if register & 0x01 == 0 {
+                    self.chr_banks[bank] & 0x1F0 | data & 0x0F
+                } else {
+                    self.chr_banks[bank] & 0x00F | (data & 0x1F) << 4
+                };
+            }
+            _ if vrc2 => {}
+            0xF000 => self.irq_latch = self.irq_latch & 0xF0 | data & 0x0F,
+            0xF001 => self.irq_latch = self.irq_latch & 0x0F | data << 4,
+            0xF002 => {
+                self.irq_enabled_after_ack = data & 0x01 != 0;
+                self.irq_enabled = data & 0x02 != 0;
+                self.irq_cycle_mode = data & 0x04 != 0;
+                self.irq_pending = false;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_latch;
+                    self.irq_prescaler = DOTS_PER_SCANLINE;
+                }
+            }
+            0xF003 => {
+                self.irq_pending = false;
+                self.irq_enabled = self.irq_enabled_after_ack;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr.read(self.chr_index(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.chr.write(index, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let saved: Self = decode_state(state)?;
+        let chr = self.chr.restore(saved.chr)?;
+        *self = Self {
+            prg_rom: std::mem::take(&mut self.prg_rom),
+            chr,
+            ..saved
+        };
+        Ok(())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn tick(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_cycle_mode {
+            self.clock_irq_counter();
+            return;
+        }
+        self.irq_prescaler -= 3;
+        if self.irq_prescaler <= 0 {
+            self.irq_prescaler += DOTS_PER_SCANLINE;
+            self.clock_irq_counter();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// 8 PRG banks and 16 CHR banks, each filled with its own number
+    fn vrc(board: VrcBoard) -> Vrc {
+        let prg_rom = (0..8).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect();
+        let chr_rom = (0..16).flat_map(|bank| [bank; CHR_BANK_SIZE]).collect();
+        Vrc::new(prg_rom, chr_rom, Mirroring::Vertical, board).unwrap()
+    }
+
+    #[test]
+    fn test_address_line_variants() {
+        let banks = |vrc: &Vrc| [0x8000, 0xA000, 0xC000, 0xE000].map(|a| vrc.cpu_peek(a));
+        // VRC4a and VRC4c both reach $9002 on mapper 21
+        for swap in [0x9004, 0x9080] {
+            let mut vrc = vrc(VrcBoard::Mapper21);
+            vrc.cpu_write(0x8000, 3);
+            vrc.cpu_write(0xA000, 5);
+            vrc.cpu_write(swap, 0x02);
+            assert_eq!(banks(&vrc), [6, 5, 3, 7]);
+        }
+
+        // Mapper 25 swaps the lines, bank 1's low nibble is at $B001 on the
+        // VRC4b and its high bits at $B00C on the VRC4d
+        let mut vrc = vrc(VrcBoard::Mapper25);
+        vrc.cpu_write(0xB000, 0x0B);
+        vrc.cpu_write(0xB001, 0x0C);
+        assert_eq!(vrc.ppu_read(0x0000), 0x0B);
+        assert_eq!(vrc.ppu_read(0x0400), 0x0C);
+        vrc.cpu_write(0xB00C, 0x01);
+        assert_eq!(vrc.chr_banks[1], 0x1C);
+    }
+
+    #[test]
+    fn test_vrc2_chr_and_mirroring() {
+        let mut vrc = vrc(VrcBoard::Mapper22);
+        // The lowest bit is dropped, so 6 selects 1KB bank 3
+        vrc.cpu_write(0xC000, 0x06);
+        assert_eq!(vrc.ppu_read(0x0800), 3);
+        vrc.cpu_write(0x9000, 0x03);
+        assert_eq!(vrc.mirroring(), Mirroring::Horizontal);
+
+        // No IRQ on the VRC2
+        vrc.cpu_write(0xF002, 0x06);
+        for _ in 0..0x200 {
+            vrc.tick();
+        }
+        assert!(!vrc.irq());
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut vrc = vrc(VrcBoard::Mapper23);
+        // Latch $FE in cycle mode, enabled again after acknowledging
+        vrc.cpu_write(0xF000, 0x0E);
+        vrc.cpu_write(0xF001, 0x0F);
+        vrc.cpu_write(0xF002, 0x07);
+        vrc.tick();
+        assert!(!vrc.irq());
+        vrc.tick();
+        assert!(vrc.irq());
+
+        vrc.cpu_write(0xF003, 0);
+        assert!(!vrc.irq());
+        vrc.tick();
+        vrc.tick();
+        assert!(vrc.irq());
+
+        // Scanline mode clocks once every 341 dots
+        vrc.cpu_write(0xF002, 0x02);
+        for _ in 0..113 {
+            vrc.tick();
+        }
+        assert_eq!(vrc.irq_counter, 0xFE);
+        vrc.tick();
+        assert_eq!(vrc.irq_counter, 0xFF);
+    }
+}
@@ -1,7 +1,46 @@
+mod apu;
+pub use apu::*;
+mod bus;
+pub use bus::*;
+mod call_graph;
+pub use call_graph::*;
+mod cheat;
+pub use cheat::*;
 mod cpu;
 pub use cpu::*;
+mod error;
+pub use error::*;
+mod frame_counter;
+pub use frame_counter::*;
 mod gamepad;
 pub use gamepad::*;
+mod history;
+pub use history::*;
+mod input;
+pub use input::*;
+mod light_state;
+pub use light_state::*;
+mod mappers;
+pub use mappers::*;
 mod opcode;
 pub use opcode::*;
+mod palette;
+pub use palette::*;
+mod ppu;
+pub use ppu::*;
+mod region;
+mod rom;
+pub use region::*;
+pub use rom::*;
+mod rtc;
+pub use rtc::*;
+mod save_state;
+mod snapshot;
+pub use save_state::*;
+pub use snapshot::*;
 mod status;
+pub use status::*;
+mod trace;
+pub use trace::*;
+mod watch;
+pub use watch::*;
@@ -1,11 +1,7 @@
-use std::{hash::Hash, sync::LazyLock};
-
-use hashbrown::HashSet;
-
-pub static CPU_OP_CODES: LazyLock<HashSet<OpCode>> = LazyLock::new(|| {
+pub static CPU_OP_CODES: OpCodeTable = {
     use AddressingMode::*;
     use Instruction::*;
-    let contents = &[
+    OpCodeTable::new(&[
         // ADC
         OpCode::new(0x69, ADC, 2, 2, Immediate),
         OpCode::new(0x65, ADC, 2, 3, ZeroPage),
@@ -46,14 +42,15 @@ pub static CPU_OP_CODES: LazyLock<HashSet<OpCode>> = LazyLock::new(|| {
         // BPL
         OpCode::new(0x10, BPL, 2, 2, Other),
         // BRK
-        OpCode::new(0x00, BRK, 0, 7, Other),
+        OpCode::new(0x00, BRK, 1, 7, Other),
         // BVC
         OpCode::new(0x50, BVC, 2, 2, Other),
         // BVS
         OpCode::new(0x70, BVS, 2, 2, Other),
         // CLC
         OpCode::new(0x18, CLC, 1, 2, Other),
-        // CLD #[NOTE] Not used in NES emulation
+        // CLD, the flag exists but the NES CPU has no decimal mode
+        OpCode::new(0xD8, CLD, 1, 2, Other),
         // CLI
         OpCode::new(0x58, CLI, 1, 2, Other),
         // CLV
@@ -79,7 +76,7 @@ pub static CPU_OP_CODES: LazyLock<HashSet<OpCode>> = LazyLock::new(|| {
         OpCode::new(0xC6, DEC, 2, 5, ZeroPage),
         OpCode::new(0xD6, DEC, 2, 6, ZeroPageX),
         OpCode::new(0xCE, DEC, 3, 6, Absolute),
-        OpCode::new(0xDE, DEC, 4, 7, AbsoluteX),
+        OpCode::new(0xDE, DEC, 3, 7, AbsoluteX),
         // DEX
         OpCode::new(0xCA, DEX, 1, 2, Other),
         // DEY
@@ -97,7 +94,7 @@ pub static CPU_OP_CODES: LazyLock<HashSet<OpCode>> = LazyLock::new(|| {
         OpCode::new(0xE6, INC, 2, 5, ZeroPage),
         OpCode::new(0xF6, INC, 2, 6, ZeroPageX),
         OpCode::new(0xEE, INC, 3, 6, Absolute),
-        OpCode::new(0xFE, INC, 3, 5, AbsoluteX),
+        OpCode::new(0xFE, INC, 3, 7, AbsoluteX),
         // INX
         OpCode::new(0xE8, INX, 1, 2, Other),
         // INY
@@ -119,7 +116,7 @@ pub static CPU_OP_CODES: LazyLock<HashSet<OpCode>> = LazyLock::new(|| {
         // LDX
         OpCode::new(0xA2, LDX, 2, 2, Immediate),
         OpCode::new(0xA6, LDX, 2, 3, ZeroPage),
-        OpCode::new(0xB2, LDX, 2, 4, ZeroPageY),
+        OpCode::new(0xB6, LDX, 2, 4, ZeroPageY),
         OpCode::new(0xAE, LDX, 3, 4, Absolute),
         OpCode::new(0xBE, LDX, 3, 4, AbsoluteY),
         // LDY
@@ -131,7 +128,7 @@ pub static CPU_OP_CODES: LazyLock<HashSet<OpCode>> = LazyLock::new(|| {
         // LSR
         OpCode::new(0x4A, LSR, 1, 2, Other),
         OpCode::new(0x46, LSR, 2, 5, ZeroPage),
-        OpCode::new(0x56, LSR, 2, 6, ZeroPageY),
+        OpCode::new(0x56, LSR, 2, 6, ZeroPageX),
         OpCode::new(0x4E, LSR, 3, 6, Absolute),
         OpCode::new(0x5E, LSR, 3, 7, AbsoluteX),
         // NOP
@@ -158,13 +155,13 @@ pub static CPU_OP_CODES: LazyLock<HashSet<OpCode>> = LazyLock::new(|| {
         OpCode::new(0x26, ROL, 2, 5, ZeroPage),
         OpCode::new(0x36, ROL, 2, 6, ZeroPageX),
         OpCode::new(0x2E, ROL, 3, 6, Absolute),
-        OpCode::new(0x3E, ROL, 3, 7, AbsoluteY),
+        OpCode::new(0x3E, ROL, 3, 7, AbsoluteX),
         // ROR
-        OpCode::new(0x6A, ROL, 1, 2, Other),
-        OpCode::new(0x66, ROL, 2, 5, ZeroPage),
-        OpCode::new(0x76, ROL, 2, 6, ZeroPageX),
-        OpCode::new(0x6E, ROL, 3, 6, Absolute),
-        OpCode::new(0x7E, ROL, 3, 7, AbsoluteY),
+        OpCode::new(0x6A, ROR, 1, 2, Other),
+        OpCode::new(0x66, ROR, 2, 5, ZeroPage),
+        OpCode::new(0x76, ROR, 2, 6, ZeroPageX),
+        OpCode::new(0x6E, ROR, 3, 6, Absolute),
+        OpCode::new(0x7E, ROR, 3, 7, AbsoluteX),
         // RTI
         OpCode::new(0x40, RTI, 1, 6, Other),
         // RTS
@@ -180,7 +177,8 @@ pub static CPU_OP_CODES: LazyLock<HashSet<OpCode>> = LazyLock::new(|| {
         OpCode::new(0xF1, SBC, 2, 5, IndirectY),
         // SEC
         OpCode::new(0x38, SEC, 1, 2, Other),
-        // SED [NOTE] Decimal mode not used in NES chip
+        // SED
+        OpCode::new(0xF8, SED, 1, 2, Other),
         // SEI
         OpCode::new(0x78, SEI, 1, 2, Other),
         // STA
@@ -211,16 +209,161 @@ pub static CPU_OP_CODES: LazyLock<HashSet<OpCode>> = LazyLock::new(|| {
         OpCode::new(0x9A, TXS, 1, 2, Other),
         // TYA
         OpCode::new(0x98, TYA, 1, 2, Other),
-    ];
-    HashSet::from_iter(contents.iter().cloned())
-});
+        // Unofficial opcodes https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+        // ALR
+        OpCode::new(0x4B, ALR, 2, 2, Immediate),
+        // ANC
+        OpCode::new(0x0B, ANC, 2, 2, Immediate),
+        OpCode::new(0x2B, ANC, 2, 2, Immediate),
+        // ARR
+        OpCode::new(0x6B, ARR, 2, 2, Immediate),
+        // AXS
+        OpCode::new(0xCB, AXS, 2, 2, Immediate),
+        // DCP
+        OpCode::new(0xC7, DCP, 2, 5, ZeroPage),
+        OpCode::new(0xD7, DCP, 2, 6, ZeroPageX),
+        OpCode::new(0xCF, DCP, 3, 6, Absolute),
+        OpCode::new(0xDF, DCP, 3, 7, AbsoluteX),
+        OpCode::new(0xDB, DCP, 3, 7, AbsoluteY),
+        OpCode::new(0xC3, DCP, 2, 8, IndirectX),
+        OpCode::new(0xD3, DCP, 2, 8, IndirectY),
+        // ISB
+        OpCode::new(0xE7, ISB, 2, 5, ZeroPage),
+        OpCode::new(0xF7, ISB, 2, 6, ZeroPageX),
+        OpCode::new(0xEF, ISB, 3, 6, Absolute),
+        OpCode::new(0xFF, ISB, 3, 7, AbsoluteX),
+        OpCode::new(0xFB, ISB, 3, 7, AbsoluteY),
+        OpCode::new(0xE3, ISB, 2, 8, IndirectX),
+        OpCode::new(0xF3, ISB, 2, 8, IndirectY),
+        // LAX
+        OpCode::new(0xA7, LAX, 2, 3, ZeroPage),
+        OpCode::new(0xB7, LAX, 2, 4, ZeroPageY),
+        OpCode::new(0xAF, LAX, 3, 4, Absolute),
+        OpCode::new(0xBF, LAX, 3, 4, AbsoluteY),
+        OpCode::new(0xA3, LAX, 2, 6, IndirectX),
+        OpCode::new(0xB3, LAX, 2, 5, IndirectY),
+        // NOP variants, the ones with operands still read them
+        OpCode::new(0x1A, NOP, 1, 2, Other),
+        OpCode::new(0x3A, NOP, 1, 2, Other),
+        OpCode::new(0x5A, NOP, 1, 2, Other),
+        OpCode::new(0x7A, NOP, 1, 2, Other),
+        OpCode::new(0xDA, NOP, 1, 2, Other),
+        OpCode::new(0xFA, NOP, 1, 2, Other),
+        OpCode::new(0x80, NOP, 2, 2, Immediate),
+        OpCode::new(0x82, NOP, 2, 2, Immediate),
+        OpCode::new(0x89, NOP, 2, 2, Immediate),
+        OpCode::new(0xC2, NOP, 2, 2, Immediate),
+        OpCode::new(0xE2, NOP, 2, 2, Immediate),
+        OpCode::new(0x04, NOP, 2, 3, ZeroPage),
+        OpCode::new(0x44, NOP, 2, 3, ZeroPage),
+        OpCode::new(0x64, NOP, 2, 3, ZeroPage),
+        OpCode::new(0x14, NOP, 2, 4, ZeroPageX),
+        OpCode::new(0x34, NOP, 2, 4, ZeroPageX),
+        OpCode::new(0x54, NOP, 2, 4, ZeroPageX),
+        OpCode::new(0x74, NOP, 2, 4, ZeroPageX),
+        OpCode::new(0xD4, NOP, 2, 4, ZeroPageX),
+        OpCode::new(0xF4, NOP, 2, 4, ZeroPageX),
+        OpCode::new(0x0C, NOP, 3, 4, Absolute),
+        OpCode::new(0x1C, NOP, 3, 4, AbsoluteX),
+        OpCode::new(0x3C, NOP, 3, 4, AbsoluteX),
+        OpCode::new(0x5C, NOP, 3, 4, AbsoluteX),
+        OpCode::new(0x7C, NOP, 3, 4, AbsoluteX),
+        OpCode::new(0xDC, NOP, 3, 4, AbsoluteX),
+        OpCode::new(0xFC, NOP, 3, 4, AbsoluteX),
+        // RLA
+        OpCode::new(0x27, RLA, 2, 5, ZeroPage),
+        OpCode::new(0x37, RLA, 2, 6, ZeroPageX),
+        OpCode::new(0x2F, RLA, 3, 6, Absolute),
+        OpCode::new(0x3F, RLA, 3, 7, AbsoluteX),
+        OpCode::new(0x3B, RLA, 3, 7, AbsoluteY),
+        OpCode::new(0x23, RLA, 2, 8, IndirectX),
+        OpCode::new(0x33, RLA, 2, 8, IndirectY),
+        // RRA
+        OpCode::new(0x67, RRA, 2, 5, ZeroPage),
+        OpCode::new(0x77, RRA, 2, 6, ZeroPageX),
+        OpCode::new(0x6F, RRA, 3, 6, Absolute),
+        OpCode::new(0x7F, RRA, 3, 7, AbsoluteX),
+        OpCode::new(0x7B, RRA, 3, 7, AbsoluteY),
+        OpCode::new(0x63, RRA, 2, 8, IndirectX),
+        OpCode::new(0x73, RRA, 2, 8, IndirectY),
+        // SAX
+        OpCode::new(0x87, SAX, 2, 3, ZeroPage),
+        OpCode::new(0x97, SAX, 2, 4, ZeroPageY),
+        OpCode::new(0x8F, SAX, 3, 4, Absolute),
+        OpCode::new(0x83, SAX, 2, 6, IndirectX),
+        // SBC
+        OpCode::new(0xEB, SBC, 2, 2, Immediate),
+        // SLO
+        OpCode::new(0x07, SLO, 2, 5, ZeroPage),
+        OpCode::new(0x17, SLO, 2, 6, ZeroPageX),
+        OpCode::new(0x0F, SLO, 3, 6, Absolute),
+        OpCode::new(0x1F, SLO, 3, 7, AbsoluteX),
+        OpCode::new(0x1B, SLO, 3, 7, AbsoluteY),
+        OpCode::new(0x03, SLO, 2, 8, IndirectX),
+        OpCode::new(0x13, SLO, 2, 8, IndirectY),
+        // SRE
+        OpCode::new(0x47, SRE, 2, 5, ZeroPage),
+        OpCode::new(0x57, SRE, 2, 6, ZeroPageX),
+        OpCode::new(0x4F, SRE, 3, 6, Absolute),
+        OpCode::new(0x5F, SRE, 3, 7, AbsoluteX),
+        OpCode::new(0x5B, SRE, 3, 7, AbsoluteY),
+        OpCode::new(0x43, SRE, 2, 8, IndirectX),
+        OpCode::new(0x53, SRE, 2, 8, IndirectY),
+    ])
+};
+
+/// Opcode metadata stored as parallel arrays indexed by the opcode byte, so
+/// decoding an instruction is a handful of byte loads rather than a hash lookup
+pub struct OpCodeTable {
+    instructions: [Option<Instruction>; 256],
+    addressing_modes: [AddressingMode; 256],
+    lens: [u8; 256],
+    cycles: [u8; 256],
+}
+
+impl OpCodeTable {
+    const fn new(op_codes: &[OpCode]) -> Self {
+        let mut table = Self {
+            instructions: [None; 256],
+            addressing_modes: [AddressingMode::Other; 256],
+            lens: [0; 256],
+            cycles: [0; 256],
+        };
+
+        let mut i = 0;
+        while i < op_codes.len() {
+            let op_code = &op_codes[i];
+            let idx = op_code.code as usize;
+            assert!(table.instructions[idx].is_none(), "duplicate opcode");
+
+            table.instructions[idx] = Some(op_code.instruction);
+            table.addressing_modes[idx] = op_code.addressing_mode;
+            table.lens[idx] = op_code.len;
+            table.cycles[idx] = op_code.cycles;
+            i += 1;
+        }
+        table
+    }
+
+    #[inline]
+    pub fn get(&self, code: u8) -> Option<OpCode> {
+        let idx = code as usize;
+        let instruction = self.instructions[idx]?;
+        Some(OpCode {
+            code,
+            instruction,
+            len: self.lens[idx],
+            cycles: self.cycles[idx],
+            addressing_mode: self.addressing_modes[idx],
+        })
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct OpCode {
     code: u8,
     pub instruction: Instruction,
     pub len: u8,
-    #[allow(dead_code)]
     cycles: u8,
     pub addressing_mode: AddressingMode,
 }
@@ -233,26 +376,14 @@ impl PartialEq for OpCode {
     }
 }
 
-impl hashbrown::Equivalent<OpCode> for u8 {
-    fn equivalent(&self, key: &OpCode) -> bool {
-        self == &key.code
-    }
-}
-
 impl PartialEq<u8> for OpCode {
     fn eq(&self, other: &u8) -> bool {
         self.code == *other
     }
 }
 
-impl Hash for OpCode {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.code.hash(state);
-    }
-}
-
 impl OpCode {
-    pub fn new(
+    pub const fn new(
         code: u8,
         instruction: Instruction,
         bytes: u8,
@@ -267,8 +398,30 @@ impl OpCode {
             addressing_mode,
         }
     }
+
+    pub fn code(&self) -> u8 {
+        self.code
+    }
+
+    /// Base cycle count, not including page crossing or branch penalties
+    pub fn cycles(&self) -> u8 {
+        self.cycles
+    }
+
+    /// Opcodes outside the documented instruction set, marked with `*` in
+    /// nestest logs
+    pub fn is_unofficial(&self) -> bool {
+        use Instruction::*;
+        match self.instruction {
+            NOP => self.code != 0xEA,
+            SBC => self.code == 0xEB,
+            ALR | ANC | ARR | AXS | DCP | ISB | LAX | RLA | RRA | SAX | SLO | SRE => true,
+            _ => false,
+        }
+    }
 }
-#[derive(Debug, Clone)]
+
+#[derive(Debug, Clone, Copy)]
 pub enum Instruction {
     ADC,
     AND,
@@ -284,6 +437,7 @@ pub enum Instruction {
     BVC,
     BVS,
     CLC,
+    CLD,
     CLI,
     CLV,
     CMP,
@@ -314,6 +468,7 @@ pub enum Instruction {
     RTS,
     SBC,
     SEC,
+    SED,
     SEI,
     STA,
     STX,
@@ -324,9 +479,35 @@ pub enum Instruction {
     TXA,
     TXS,
     TYA,
+
+    // Unofficial
+    /// AND then LSR A
+    ALR,
+    /// AND, copying the result's bit 7 into carry
+    ANC,
+    /// AND then ROR A, with odd carry and overflow results
+    ARR,
+    /// X = (A & X) - operand, without borrow
+    AXS,
+    /// DEC then CMP
+    DCP,
+    /// INC then SBC
+    ISB,
+    /// LDA and LDX at once
+    LAX,
+    /// ROL then AND
+    RLA,
+    /// ROR then ADC
+    RRA,
+    /// Stores A & X
+    SAX,
+    /// ASL then ORA
+    SLO,
+    /// LSR then EOR
+    SRE,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AddressingMode {
     Immediate,
     ZeroPage,
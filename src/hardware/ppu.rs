@@ -0,0 +1,816 @@
+use std::{cell::RefCell, rc::Rc};
+
+use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    frame::Frame,
+    hardware::{Cartridge, EmuError, Mirroring, Nrom, Region, SYSTEM_PALETTE},
+};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+const DOTS_PER_SCANLINE: usize = 341;
+/// Where a visible line is drawn, once the background fetches for it are
+/// done and the horizontal scroll for the next one is copied over
+const RENDER_DOT: usize = 257;
+
+const NAMETABLE_SIZE: u16 = 0x0400;
+
+bitflags! {
+    /// PPUCTRL ($2000) https://www.nesdev.org/wiki/PPU_registers#PPUCTRL
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct PpuCtrl: u8 {
+        const NAMETABLE_X          = 0b00000001;
+        const NAMETABLE_Y          = 0b00000010;
+        /// Add 32 to the VRAM address after each PPUDATA access instead of 1
+        const VRAM_INCREMENT       = 0b00000100;
+        const SPRITE_PATTERN       = 0b00001000;
+        const BACKGROUND_PATTERN   = 0b00010000;
+        const TALL_SPRITES         = 0b00100000;
+        const MASTER_SLAVE         = 0b01000000;
+        const GENERATE_NMI         = 0b10000000;
+    }
+}
+
+bitflags! {
+    /// PPUMASK ($2001) https://www.nesdev.org/wiki/PPU_registers#PPUMASK
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct PpuMask: u8 {
+        const GREYSCALE            = 0b00000001;
+        const BACKGROUND_LEFT      = 0b00000010;
+        const SPRITES_LEFT         = 0b00000100;
+        const SHOW_BACKGROUND      = 0b00001000;
+        const SHOW_SPRITES         = 0b00010000;
+        const EMPHASISE_RED        = 0b00100000;
+        const EMPHASISE_GREEN      = 0b01000000;
+        const EMPHASISE_BLUE       = 0b10000000;
+    }
+}
+
+bitflags! {
+    /// PPUSTATUS ($2002) https://www.nesdev.org/wiki/PPU_registers#PPUSTATUS
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct PpuStatus: u8 {
+        const SPRITE_OVERFLOW      = 0b00100000;
+        const SPRITE_ZERO_HIT      = 0b01000000;
+        const VBLANK               = 0b10000000;
+    }
+}
+
+/// Everything in [`Ppu`] but the cartridge, for savestates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct PpuState {
+    vram: Vec<u8>,
+    palette: Vec<u8>,
+    oam: Vec<u8>,
+    ctrl: u8,
+    mask: u8,
+    status: u8,
+    oam_addr: u8,
+    scroll_x: u8,
+    scroll_y: u8,
+    frame_scroll_y: u16,
+    vram_addr: u16,
+    write_latch: bool,
+    read_buffer: u8,
+    scanline: u16,
+    dot: usize,
+    frame: u64,
+    nmi_pending: bool,
+}
+
+/// The picture processing unit: the registers the CPU sees at
+/// $2000-$2007, its own VRAM, palette RAM and sprite memory (OAM)
+pub struct Ppu {
+    /// Provides the pattern tables and decides the nametable mirroring
+    cartridge: Cartridge,
+    /// Decides the length of the frame and where vblank starts
+    region: Region,
+    /// Room for four nametables, only four-screen cartridges use all of it
+    vram: [u8; 4 * NAMETABLE_SIZE as usize],
+    pub palette: [u8; 32],
+    pub oam: [u8; 256],
+
+    pub ctrl: PpuCtrl,
+    pub mask: PpuMask,
+    pub status: PpuStatus,
+    oam_addr: u8,
+    scroll_x: u8,
+    scroll_y: u8,
+    /// Vertical scroll of the frame being drawn, in the 512x480 plane of
+    /// the four nametables. Like on hardware, writing PPUSCROLL or PPUCTRL
+    /// mid-frame only moves it from the next frame on.
+    frame_scroll_y: u16,
+    vram_addr: u16,
+    /// Shared first/second write toggle of PPUSCROLL and PPUADDR
+    write_latch: bool,
+    /// PPUDATA reads return the previous value for anything but palettes
+    read_buffer: u8,
+
+    scanline: u16,
+    dot: usize,
+    frame: u64,
+    nmi_pending: bool,
+    /// Colours of the lines drawn so far, as [`SYSTEM_PALETTE`] indexes
+    pixels: Vec<u8>,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new(Rc::new(RefCell::new(Nrom::default())))
+    }
+}
+
+impl Ppu {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Self {
+            cartridge,
+            region: Region::default(),
+            vram: [0; 4 * NAMETABLE_SIZE as usize],
+            palette: [0; 32],
+            oam: [0; 256],
+            ctrl: PpuCtrl::default(),
+            mask: PpuMask::default(),
+            status: PpuStatus::default(),
+            oam_addr: 0,
+            scroll_x: 0,
+            scroll_y: 0,
+            frame_scroll_y: 0,
+            vram_addr: 0,
+            write_latch: false,
+            read_buffer: 0,
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            nmi_pending: false,
+            pixels: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Advances the PPU by `dots` PPU cycles, see
+    /// [`Region::dots_per_cycle`]. Returns whether a frame finished.
+    pub fn tick(&mut self, dots: usize) -> bool {
+        let mut frame_done = false;
+        let mut dots = dots;
+        while dots > 0 {
+            let step = dots.min(DOTS_PER_SCANLINE - self.dot);
+            let before = self.dot;
+            self.dot += step;
+            dots -= step;
+            if let Some(rise) = self.a12_rise_dot()
+                && before < rise
+                && self.dot >= rise
+            {
+                self.cartridge.borrow_mut().clock_scanline();
+            }
+            if self.scanline < SCREEN_HEIGHT as u16 && before < RENDER_DOT && self.dot >= RENDER_DOT
+            {
+                self.render_line(self.scanline as usize);
+            }
+            if self.dot < DOTS_PER_SCANLINE {
+                continue;
+            }
+
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == self.region.vblank_scanline() {
+                self.status.insert(PpuStatus::VBLANK);
+                if self.ctrl.contains(PpuCtrl::GENERATE_NMI) {
+                    self.nmi_pending = true;
+                }
+            } else if self.scanline == self.region.scanlines() - 1 {
+                // Pre-render line
+                self.status.remove(
+                    PpuStatus::VBLANK | PpuStatus::SPRITE_ZERO_HIT | PpuStatus::SPRITE_OVERFLOW,
+                );
+            } else if self.scanline == self.region.scanlines() {
+                self.scanline = 0;
+                self.frame += 1;
+                let base_y = self.ctrl.contains(PpuCtrl::NAMETABLE_Y) as u16 * SCREEN_HEIGHT as u16;
+                self.frame_scroll_y = base_y + self.scroll_y as u16;
+                frame_done = true;
+            }
+        }
+
+        if self.sprite_zero_hit() {
+            self.status.insert(PpuStatus::SPRITE_ZERO_HIT);
+        }
+        frame_done
+    }
+
+    /// The dot on the current scanline where pattern fetches switch from
+    /// the table at $0000 to the one at $1000, if they do. Sprite fetches
+    /// start at dot 257 and background fetches for the next line at 321.
+    fn a12_rise_dot(&self) -> Option<usize> {
+        let rendering = self
+            .mask
+            .intersects(PpuMask::SHOW_BACKGROUND | PpuMask::SHOW_SPRITES);
+        let visible =
+            self.scanline < SCREEN_HEIGHT as u16 || self.scanline == self.region.scanlines() - 1;
+        if !rendering || !visible {
+            return None;
+        }
+        let sprites_high = self
+            .ctrl
+            .intersects(PpuCtrl::SPRITE_PATTERN | PpuCtrl::TALL_SPRITES);
+        match (
+            self.ctrl.contains(PpuCtrl::BACKGROUND_PATTERN),
+            sprites_high,
+        ) {
+            (false, true) => Some(260),
+            (true, false) => Some(324),
+            _ => None,
+        }
+    }
+
+    /// Approximates sprite 0 hit by its position rather than comparing
+    /// pixels, which is enough for the status bar splits games use it for
+    fn sprite_zero_hit(&self) -> bool {
+        let (y, x) = (self.oam[0] as u16, self.oam[3] as usize);
+        self.mask
+            .contains(PpuMask::SHOW_BACKGROUND | PpuMask::SHOW_SPRITES)
+            && self.scanline > y
+            && self.scanline <= SCREEN_HEIGHT as u16
+            && self.dot >= x
+    }
+
+    /// Returns and clears a pending NMI request
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    /// Number of frames completed since power on
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    /// Dot within the current scanline
+    pub fn dot(&self) -> usize {
+        self.dot
+    }
+
+    /// A CPU read of register `$2000 + register`
+    pub fn read_register(&mut self, register: u16) -> u8 {
+        match register {
+            2 => {
+                let status = self.status.bits();
+                self.status.remove(PpuStatus::VBLANK);
+                self.write_latch = false;
+                status
+            }
+            4 => self.oam[self.oam_addr as usize],
+            7 => {
+                let addr = self.vram_addr;
+                self.increment_vram_addr();
+                let value = self.read_vram(addr);
+                if addr >= 0x3F00 {
+                    // Palette reads skip the buffer, which picks up the
+                    // nametable byte underneath instead
+                    self.read_buffer = self.read_vram(addr - 0x1000);
+                    value
+                } else {
+                    std::mem::replace(&mut self.read_buffer, value)
+                }
+            }
+            // Write-only
+            _ => 0,
+        }
+    }
+
+    /// What [`Ppu::read_register`] would return, without side effects
+    pub fn peek_register(&self, register: u16) -> u8 {
+        match register {
+            2 => self.status.bits(),
+            4 => self.oam[self.oam_addr as usize],
+            7 if self.vram_addr >= 0x3F00 => self.read_vram(self.vram_addr),
+            7 => self.read_buffer,
+            _ => 0,
+        }
+    }
+
+    /// A CPU write to register `$2000 + register`
+    pub fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                let was_enabled = self.ctrl.contains(PpuCtrl::GENERATE_NMI);
+                self.ctrl = PpuCtrl::from_bits_truncate(data);
+                // Enabling NMIs during vblank fires one straight away
+                if !was_enabled
+                    && self.ctrl.contains(PpuCtrl::GENERATE_NMI)
+                    && self.status.contains(PpuStatus::VBLANK)
+                {
+                    self.nmi_pending = true;
+                }
+            }
+            1 => self.mask = PpuMask::from_bits_truncate(data),
+            3 => self.oam_addr = data,
+            4 => {
+                self.oam[self.oam_addr as usize] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            5 => {
+                if self.write_latch {
+                    self.scroll_y = data;
+                } else {
+                    self.scroll_x = data;
+                }
+                self.write_latch = !self.write_latch;
+            }
+            6 => {
+                if self.write_latch {
+                    self.vram_addr = (self.vram_addr & 0xFF00) | data as u16;
+                } else {
+                    self.vram_addr = ((data as u16) << 8 | (self.vram_addr & 0x00FF)) & 0x3FFF;
+                }
+                self.write_latch = !self.write_latch;
+            }
+            7 => {
+                self.write_vram(self.vram_addr, data);
+                self.increment_vram_addr();
+            }
+            _ => {}
+        }
+    }
+
+    /// OAM DMA ($4014): copies a whole page of CPU memory into OAM
+    pub fn write_oam_dma(&mut self, page: &[u8; 256]) {
+        for value in page {
+            self.oam[self.oam_addr as usize] = *value;
+            self.oam_addr = self.oam_addr.wrapping_add(1);
+        }
+    }
+
+    fn increment_vram_addr(&mut self) {
+        let step = if self.ctrl.contains(PpuCtrl::VRAM_INCREMENT) {
+            32
+        } else {
+            1
+        };
+        self.vram_addr = self.vram_addr.wrapping_add(step) & 0x3FFF;
+    }
+
+    pub(crate) fn save_state(&self) -> PpuState {
+        PpuState {
+            vram: self.vram.to_vec(),
+            palette: self.palette.to_vec(),
+            oam: self.oam.to_vec(),
+            ctrl: self.ctrl.bits(),
+            mask: self.mask.bits(),
+            status: self.status.bits(),
+            oam_addr: self.oam_addr,
+            scroll_x: self.scroll_x,
+            scroll_y: self.scroll_y,
+            frame_scroll_y: self.frame_scroll_y,
+            vram_addr: self.vram_addr,
+            write_latch: self.write_latch,
+            read_buffer: self.read_buffer,
+            scanline: self.scanline,
+            dot: self.dot,
+            frame: self.frame,
+            nmi_pending: self.nmi_pending,
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &PpuState) -> Result<(), EmuError> {
+        if state.vram.len() != self.vram.len()
+            || state.palette.len() != self.palette.len()
+            || state.oam.len() != self.oam.len()
+        {
+            return Err(EmuError::InvalidSaveState(
+                "PPU memory has the wrong size".to_string(),
+            ));
+        }
+        self.vram.copy_from_slice(&state.vram);
+        self.palette.copy_from_slice(&state.palette);
+        self.oam.copy_from_slice(&state.oam);
+        self.ctrl = PpuCtrl::from_bits_retain(state.ctrl);
+        self.mask = PpuMask::from_bits_retain(state.mask);
+        self.status = PpuStatus::from_bits_retain(state.status);
+        self.oam_addr = state.oam_addr;
+        self.scroll_x = state.scroll_x;
+        self.scroll_y = state.scroll_y;
+        self.frame_scroll_y = state.frame_scroll_y;
+        self.vram_addr = state.vram_addr;
+        self.write_latch = state.write_latch;
+        self.read_buffer = state.read_buffer;
+        self.scanline = state.scanline;
+        self.dot = state.dot;
+        self.frame = state.frame;
+        self.nmi_pending = state.nmi_pending;
+        Ok(())
+    }
+
+    /// Swaps the cartridge without resetting anything else
+    pub fn set_cartridge(&mut self, cartridge: Cartridge) {
+        self.cartridge = cartridge;
+    }
+
+    /// Reads the PPU address space, with all the mirrors applied
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        match addr & 0x3FFF {
+            addr @ 0x0000..=0x1FFF => self.cartridge.borrow().ppu_read(addr),
+            addr @ 0x2000..=0x3EFF => self.vram[self.nametable_index(addr)],
+            addr => self.palette[palette_index(addr)],
+        }
+    }
+
+    pub fn write_vram(&mut self, addr: u16, data: u8) {
+        match addr & 0x3FFF {
+            addr @ 0x0000..=0x1FFF => self.cartridge.borrow_mut().ppu_write(addr, data),
+            addr @ 0x2000..=0x3EFF => {
+                let idx = self.nametable_index(addr);
+                self.vram[idx] = data;
+            }
+            addr => self.palette[palette_index(addr)] = data,
+        }
+    }
+
+    /// Maps $2000-$3EFF onto the nametables the cartridge actually wires up
+    fn nametable_index(&self, addr: u16) -> usize {
+        let offset = (addr - 0x2000) % (4 * NAMETABLE_SIZE);
+        let table = offset / NAMETABLE_SIZE;
+        let physical = match (self.cartridge.borrow().mirroring(), table) {
+            (Mirroring::FourScreen, table) => table,
+            (Mirroring::Vertical, table) => table % 2,
+            (Mirroring::Horizontal, table) => table / 2,
+            (Mirroring::SingleScreenLower, _) => 0,
+            (Mirroring::SingleScreenUpper, _) => 1,
+        };
+        (physical * NAMETABLE_SIZE + offset % NAMETABLE_SIZE) as usize
+    }
+
+    /// Copies the picture into `frame`, which must be [`SCREEN_WIDTH`] x
+    /// [`SCREEN_HEIGHT`]. Complete right after [`Ppu::tick`] finishes a
+    /// frame; each line was drawn with the registers as they were on it,
+    /// so mid-frame scroll splits show up.
+    pub fn render_frame(&self, frame: &mut Frame) {
+        assert_eq!(
+            (frame.width(), frame.height()),
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        );
+
+        for (idx, colour) in self.pixels.iter().enumerate() {
+            frame.set_pixel(
+                idx % SCREEN_WIDTH,
+                idx / SCREEN_WIDTH,
+                SYSTEM_PALETTE[*colour as usize],
+            );
+        }
+    }
+
+    /// Draws visible line `y` with the current nametables, sprites and
+    /// registers
+    fn render_line(&mut self, y: usize) {
+        // Palette RAM indexes for the line, 0 is transparent
+        let mut background = [0u8; SCREEN_WIDTH];
+        let mut sprites = [0u8; SCREEN_WIDTH];
+        let mut sprite_behind = [false; SCREEN_WIDTH];
+
+        if self.mask.contains(PpuMask::SHOW_BACKGROUND) {
+            self.render_background_line(y, &mut background);
+        }
+        if self.mask.contains(PpuMask::SHOW_SPRITES) {
+            self.render_sprite_line(y, &mut sprites, &mut sprite_behind);
+        }
+
+        for x in 0..SCREEN_WIDTH {
+            let mut background = background[x];
+            let mut sprite = sprites[x];
+            if x < 8 && !self.mask.contains(PpuMask::BACKGROUND_LEFT) {
+                background = 0;
+            }
+            if x < 8 && !self.mask.contains(PpuMask::SPRITES_LEFT) {
+                sprite = 0;
+            }
+
+            let opaque = |idx: u8| idx & 0b11 != 0;
+            let idx = match (opaque(background), opaque(sprite)) {
+                (true, true) if sprite_behind[x] => background,
+                (_, true) => sprite,
+                (true, false) => background,
+                (false, false) => 0,
+            };
+            let mut colour = self.palette[palette_index(0x3F00 + idx as u16)];
+            if self.mask.contains(PpuMask::GREYSCALE) {
+                colour &= 0x30;
+            }
+            self.pixels[y * SCREEN_WIDTH + x] = colour & 0x3F;
+        }
+    }
+
+    fn render_background_line(&self, y: usize, line: &mut [u8; SCREEN_WIDTH]) {
+        let pattern_base = if self.ctrl.contains(PpuCtrl::BACKGROUND_PATTERN) {
+            0x1000
+        } else {
+            0
+        };
+        // Position in the 512x480 plane made of the four nametables
+        let base_x = self.ctrl.contains(PpuCtrl::NAMETABLE_X) as usize * SCREEN_WIDTH;
+        let world_y = (self.frame_scroll_y as usize + y) % (2 * SCREEN_HEIGHT);
+        let (table_y, row) = (world_y / SCREEN_HEIGHT, world_y % SCREEN_HEIGHT);
+
+        for (x, pixel) in line.iter_mut().enumerate() {
+            let world_x = (base_x + self.scroll_x as usize + x) % (2 * SCREEN_WIDTH);
+            let (table_x, col) = (world_x / SCREEN_WIDTH, world_x % SCREEN_WIDTH);
+            let nametable = 0x2000 + (table_y * 2 + table_x) as u16 * NAMETABLE_SIZE;
+
+            let (tile_x, tile_y) = (col / 8, row / 8);
+            let tile = self.read_vram(nametable + (tile_y * 32 + tile_x) as u16);
+            let attribute =
+                self.read_vram(nametable + 0x3C0 + (tile_y / 4 * 8 + tile_x / 4) as u16);
+            let shift = ((tile_y % 4) / 2 * 2 + (tile_x % 4) / 2) * 2;
+            let palette = (attribute >> shift) & 0b11;
+
+            let colour = self.pattern_pixel(pattern_base + tile as u16 * 16, col % 8, row % 8);
+            *pixel = if colour == 0 {
+                0
+            } else {
+                palette << 2 | colour
+            };
+        }
+    }
+
+    fn render_sprite_line(
+        &self,
+        y: usize,
+        line: &mut [u8; SCREEN_WIDTH],
+        behind: &mut [bool; SCREEN_WIDTH],
+    ) {
+        let height = if self.ctrl.contains(PpuCtrl::TALL_SPRITES) {
+            16
+        } else {
+            8
+        };
+
+        // Lower OAM indexes win, so draw them last
+        for sprite in self.oam.chunks_exact(4).rev() {
+            let top = sprite[0] as usize + 1;
+            if y < top || y >= top + height {
+                continue;
+            }
+            let (tile, attributes, left) = (sprite[1], sprite[2], sprite[3] as usize);
+            let flip_h = attributes & 0b0100_0000 != 0;
+            let flip_v = attributes & 0b1000_0000 != 0;
+
+            let mut row = y - top;
+            if flip_v {
+                row = height - 1 - row;
+            }
+            let tile_addr = if height == 16 {
+                let table = (tile & 1) as u16 * 0x1000;
+                table + ((tile & 0xFE) as u16 + (row / 8) as u16) * 16
+            } else {
+                let table = if self.ctrl.contains(PpuCtrl::SPRITE_PATTERN) {
+                    0x1000
+                } else {
+                    0
+                };
+                table + tile as u16 * 16
+            };
+
+            for col in 0..8 {
+                let x = left + col;
+                if x >= SCREEN_WIDTH {
+                    break;
+                }
+                let colour =
+                    self.pattern_pixel(tile_addr, if flip_h { 7 - col } else { col }, row % 8);
+                if colour != 0 {
+                    // Sprite palettes are the second half of palette RAM
+                    line[x] = 0x10 | (attributes & 0b11) << 2 | colour;
+                    behind[x] = attributes & 0b0010_0000 != 0;
+                }
+            }
+        }
+    }
+
+    /// 2-bit colour of a pixel in the 8x8 tile at `tile_addr`
+    fn pattern_pixel(&self, tile_addr: u16, x: usize, y: usize) -> u8 {
+        let lo = self.read_vram(tile_addr + y as u16);
+        let hi = self.read_vram(tile_addr + y as u16 + 8);
+        let bit = 7 - x;
+        ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1)
+    }
+}
+
+/// Palette RAM has 32 entries mirrored through $3F00-$3FFF, and the
+/// sprite backdrop entries mirror the background ones
+fn palette_index(addr: u16) -> usize {
+    let idx = (addr & 0x1F) as usize;
+    match idx {
+        0x10 | 0x14 | 0x18 | 0x1C => idx - 0x10,
+        _ => idx,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::{Mapper, Mmc3};
+
+    use super::*;
+
+    fn ppu_with(chr_rom: Vec<u8>, mirroring: Mirroring) -> Ppu {
+        let nrom = Nrom::new(vec![0; 0x8000], chr_rom, mirroring).unwrap();
+        Ppu::new(Rc::new(RefCell::new(nrom)))
+    }
+
+    fn write_addr(ppu: &mut Ppu, addr: u16) {
+        ppu.write_register(6, (addr >> 8) as u8);
+        ppu.write_register(6, addr as u8);
+    }
+
+    #[test]
+    fn test_ppudata_read_is_buffered() {
+        let mut ppu = Ppu::default();
+        ppu.write_vram(0x2305, 0x66);
+        ppu.write_vram(0x2306, 0x77);
+
+        write_addr(&mut ppu, 0x2305);
+        ppu.read_register(7);
+        assert_eq!(ppu.read_register(7), 0x66);
+        assert_eq!(ppu.read_register(7), 0x77);
+    }
+
+    #[test]
+    fn test_ppudata_write_increments() {
+        let mut ppu = Ppu::default();
+        ppu.write_register(0, PpuCtrl::VRAM_INCREMENT.bits());
+        write_addr(&mut ppu, 0x2000);
+        ppu.write_register(7, 0x11);
+        ppu.write_register(7, 0x22);
+        assert_eq!(ppu.read_vram(0x2000), 0x11);
+        assert_eq!(ppu.read_vram(0x2020), 0x22);
+    }
+
+    #[test]
+    fn test_status_read_resets_latch() {
+        let mut ppu = Ppu::default();
+        ppu.status.insert(PpuStatus::VBLANK);
+        ppu.write_register(6, 0x21);
+        assert_eq!(ppu.peek_register(2), 0x80);
+
+        assert_eq!(ppu.read_register(2), 0x80);
+        assert_eq!(ppu.read_register(2), 0x00);
+        // The next PPUADDR write is treated as the high byte again
+        write_addr(&mut ppu, 0x2400);
+        ppu.write_register(7, 0x99);
+        assert_eq!(ppu.read_vram(0x2400), 0x99);
+    }
+
+    #[test]
+    fn test_nametable_mirroring() {
+        let mut horizontal = ppu_with(vec![], Mirroring::Horizontal);
+        horizontal.write_vram(0x2005, 1);
+        horizontal.write_vram(0x2805, 2);
+        assert_eq!(horizontal.read_vram(0x2405), 1);
+        assert_eq!(horizontal.read_vram(0x2C05), 2);
+
+        let mut vertical = ppu_with(vec![], Mirroring::Vertical);
+        vertical.write_vram(0x2005, 1);
+        vertical.write_vram(0x2405, 2);
+        assert_eq!(vertical.read_vram(0x2805), 1);
+        assert_eq!(vertical.read_vram(0x3C05), 2);
+    }
+
+    #[test]
+    fn test_palette_mirrors() {
+        let mut ppu = Ppu::default();
+        ppu.write_vram(0x3F10, 0x0F);
+        assert_eq!(ppu.read_vram(0x3F00), 0x0F);
+        assert_eq!(ppu.read_vram(0x3F20), 0x0F);
+    }
+
+    #[test]
+    fn test_vblank_and_nmi() {
+        let mut ppu = Ppu::default();
+        ppu.write_register(0, PpuCtrl::GENERATE_NMI.bits());
+
+        assert!(!ppu.tick(240 * DOTS_PER_SCANLINE));
+        assert!(!ppu.status.contains(PpuStatus::VBLANK));
+        ppu.tick(DOTS_PER_SCANLINE);
+        assert!(ppu.status.contains(PpuStatus::VBLANK));
+        assert!(ppu.take_nmi());
+        assert!(!ppu.take_nmi());
+
+        assert!(ppu.tick(21 * DOTS_PER_SCANLINE));
+        assert_eq!((ppu.frame(), ppu.scanline()), (1, 0));
+        assert!(!ppu.status.contains(PpuStatus::VBLANK));
+    }
+
+    #[test]
+    fn test_a12_clocks_scanline_counter() {
+        let mut mmc3 = Mmc3::new(vec![0; 0x8000], vec![], Mirroring::Vertical).unwrap();
+        mmc3.cpu_write(0xC000, 240);
+        mmc3.cpu_write(0xC001, 0);
+        mmc3.cpu_write(0xE001, 0);
+        let cartridge: Cartridge = Rc::new(RefCell::new(mmc3));
+        let mut ppu = Ppu::new(cartridge.clone());
+        ppu.write_register(0, PpuCtrl::SPRITE_PATTERN.bits());
+        ppu.write_register(1, PpuMask::SHOW_BACKGROUND.bits());
+
+        // One reload and 240 decrements over the visible and pre-render lines
+        ppu.tick(261 * DOTS_PER_SCANLINE);
+        assert!(!cartridge.borrow().irq());
+        assert!(ppu.tick(DOTS_PER_SCANLINE));
+        assert!(cartridge.borrow().irq());
+    }
+
+    #[test]
+    fn test_dendy_vblank_is_delayed() {
+        let mut ppu = Ppu::default();
+        ppu.set_region(Region::Dendy);
+
+        ppu.tick(241 * DOTS_PER_SCANLINE);
+        assert!(!ppu.status.contains(PpuStatus::VBLANK));
+        ppu.tick(50 * DOTS_PER_SCANLINE);
+        assert!(ppu.status.contains(PpuStatus::VBLANK));
+
+        assert!(ppu.tick(21 * DOTS_PER_SCANLINE));
+        assert_eq!((ppu.frame(), ppu.scanline()), (1, 0));
+    }
+
+    #[test]
+    fn test_render_background_and_sprite() {
+        // Tile 1 is solid colour 1, tile 2 solid colour 3
+        let mut chr = vec![0; 0x2000];
+        chr[16..24].fill(0xFF);
+        chr[32..48].fill(0xFF);
+        let mut ppu = ppu_with(chr, Mirroring::Vertical);
+        ppu.mask = PpuMask::all() - PpuMask::GREYSCALE;
+
+        ppu.write_vram(0x3F00, 0x0F);
+        ppu.write_vram(0x3F01, 0x30);
+        ppu.write_vram(0x3F13, 0x16);
+        // Top left background tile, and a sprite at (16, 8) using palette 0
+        ppu.write_vram(0x2000, 1);
+        ppu.oam[0..4].copy_from_slice(&[7, 2, 0, 16]);
+
+        ppu.tick(262 * DOTS_PER_SCANLINE);
+        let mut frame = Frame::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+        ppu.render_frame(&mut frame);
+
+        let pixel = |x: usize, y: usize| {
+            let base = (y * SCREEN_WIDTH + x) * 3;
+            let data = frame.data();
+            (data[base], data[base + 1], data[base + 2])
+        };
+        assert_eq!(pixel(0, 0), SYSTEM_PALETTE[0x30]);
+        assert_eq!(pixel(8, 0), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(pixel(16, 8), SYSTEM_PALETTE[0x16]);
+        assert_eq!(pixel(23, 15), SYSTEM_PALETTE[0x16]);
+        assert_eq!(pixel(24, 8), SYSTEM_PALETTE[0x0F]);
+    }
+
+    #[test]
+    fn test_mid_frame_scroll_split() {
+        // Tile 1 is solid colour 1
+        let mut chr = vec![0; 0x2000];
+        chr[16..24].fill(0xFF);
+        let mut ppu = ppu_with(chr, Mirroring::Vertical);
+        ppu.write_register(
+            1,
+            PpuMask::SHOW_BACKGROUND.bits() | PpuMask::BACKGROUND_LEFT.bits(),
+        );
+        ppu.write_vram(0x3F00, 0x0F);
+        ppu.write_vram(0x3F01, 0x30);
+        // Tile 1 in the second column of the top 6 tile rows
+        for row in 0..6 {
+            ppu.write_vram(0x2001 + row * 32, 1);
+        }
+
+        // A status bar over the first 32 lines, then the playfield scrolled
+        // 8 pixels, as after a sprite 0 hit. The vertical scroll waits for
+        // the next frame.
+        ppu.tick(32 * DOTS_PER_SCANLINE);
+        ppu.write_register(5, 8);
+        ppu.write_register(5, 16);
+        assert!(ppu.tick(230 * DOTS_PER_SCANLINE));
+
+        let mut frame = Frame::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+        ppu.render_frame(&mut frame);
+        let pixel = |x: usize, y: usize| {
+            let base = (y * SCREEN_WIDTH + x) * 3;
+            let data = frame.data();
+            (data[base], data[base + 1], data[base + 2])
+        };
+        assert_eq!(pixel(0, 31), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(pixel(8, 31), SYSTEM_PALETTE[0x30]);
+        assert_eq!(pixel(0, 32), SYSTEM_PALETTE[0x30]);
+        assert_eq!(pixel(0, 47), SYSTEM_PALETTE[0x30]);
+        assert_eq!(pixel(0, 48), SYSTEM_PALETTE[0x0F]);
+    }
+}
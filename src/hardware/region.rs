@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// The console's video standard, which sets how fast the CPU runs and how
+/// the PPU's frame is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    /// Famiclone hybrid: a PAL frame and CPU speed, but vblank starts on
+    /// the same scanline count after rendering as on NTSC and the PPU runs
+    /// three dots per CPU cycle. Many unlicensed games rely on the extra
+    /// time this leaves between the NMI and the next frame.
+    Dendy,
+}
+
+impl Region {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    pub fn cpu_clock_hz(&self) -> u32 {
+        match self {
+            Region::Ntsc => 1_789_773,
+            Region::Pal => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal | Region::Dendy => 50.0070,
+        }
+    }
+
+    /// Scanlines per frame, including vblank and the pre-render line
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// The scanline vblank and the NMI start on. Dendy delays it by the 50
+    /// extra lines so that vblank itself is as long as on NTSC.
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// PPU dots per CPU cycle as a fraction, 3.2 on PAL
+    pub fn dots_per_cycle(&self) -> (usize, usize) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::EmuError;
+
+const NES_TAG: [u8; 4] = [b'N', b'E', b'S', 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_PAGE_SIZE: usize = 0x4000;
+const CHR_ROM_PAGE_SIZE: usize = 0x2000;
+
+/// How the cartridge wires the two physical nametables into the PPU's
+/// four logical ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mirroring {
+    Vertical,
+    Horizontal,
+    /// The cartridge provides its own VRAM for all four
+    FourScreen,
+    /// All four show the first physical nametable, only mappers switch to this
+    SingleScreenLower,
+    /// All four show the second physical nametable
+    SingleScreenUpper,
+}
+
+/// A cartridge image parsed from an iNES file
+#[derive(Debug, Clone)]
+pub struct Rom {
+    pub prg_rom: Vec<u8>,
+    /// Empty when the cartridge uses CHR RAM instead
+    pub chr_rom: Vec<u8>,
+    pub mapper: u8,
+    pub mirroring: Mirroring,
+    /// 512 bytes meant for 0x7000, used by some copier dumps
+    pub trainer: Option<Vec<u8>>,
+    /// Has battery-backed PRG RAM at 0x6000
+    pub battery: bool,
+}
+
+impl Rom {
+    pub fn load(path: &Path) -> Result<Self, EmuError> {
+        let raw = std::fs::read(path).map_err(|err| EmuError::Io(err.to_string()))?;
+        Self::new(&raw)
+    }
+
+    pub fn new(raw: &[u8]) -> Result<Self, EmuError> {
+        if raw.len() < HEADER_SIZE || raw[0..4] != NES_TAG {
+            return Err(EmuError::InvalidRom("not an iNES file".to_string()));
+        }
+
+        let flags6 = raw[6];
+        let flags7 = raw[7];
+        let mirroring = match (flags6 & 0b1000 != 0, flags6 & 0b1 != 0) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+        // Old dumping tools wrote junk like "DiskDude!" into bytes 7-15,
+        // which would corrupt the upper mapper bits
+        let mapper_hi = if raw[12..16].iter().all(|byte| *byte == 0) || flags7 & 0x0C == 0x08 {
+            flags7 & 0xF0
+        } else {
+            0
+        };
+        let mapper = mapper_hi | (flags6 >> 4);
+
+        let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
+        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+        if prg_rom_size == 0 {
+            return Err(EmuError::InvalidRom("no PRG ROM".to_string()));
+        }
+
+        let has_trainer = flags6 & 0b100 != 0;
+        let prg_rom_start = HEADER_SIZE + if has_trainer { TRAINER_SIZE } else { 0 };
+        let chr_rom_start = prg_rom_start + prg_rom_size;
+        if raw.len() < chr_rom_start + chr_rom_size {
+            return Err(EmuError::InvalidRom(format!(
+                "truncated: header expects {} bytes but the file has {}",
+                chr_rom_start + chr_rom_size,
+                raw.len()
+            )));
+        }
+
+        Ok(Self {
+            prg_rom: raw[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom: raw[chr_rom_start..chr_rom_start + chr_rom_size].to_vec(),
+            mapper,
+            mirroring,
+            trainer: has_trainer.then(|| raw[HEADER_SIZE..prg_rom_start].to_vec()),
+            battery: flags6 & 0b10 != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// A minimal iNES image with the given header flags and blank banks
+    pub(crate) fn ines(prg_pages: u8, chr_pages: u8, flags6: u8, flags7: u8) -> Vec<u8> {
+        let mut raw = vec![
+            b'N', b'E', b'S', 0x1A, prg_pages, chr_pages, flags6, flags7, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        if flags6 & 0b100 != 0 {
+            raw.extend([0xEE; TRAINER_SIZE]);
+        }
+        raw.extend(vec![0xAA; prg_pages as usize * PRG_ROM_PAGE_SIZE]);
+        raw.extend(vec![0xCC; chr_pages as usize * CHR_ROM_PAGE_SIZE]);
+        raw
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let rom = Rom::new(&ines(2, 1, 0x31, 0x40)).unwrap();
+        assert_eq!(rom.prg_rom.len(), 0x8000);
+        assert_eq!(rom.chr_rom.len(), 0x2000);
+        assert_eq!(rom.mapper, 0x43);
+        assert_eq!(rom.mirroring, Mirroring::Vertical);
+        assert!(rom.trainer.is_none());
+        assert!(!rom.battery);
+    }
+
+    #[test]
+    fn test_trainer_is_skipped() {
+        let rom = Rom::new(&ines(1, 0, 0b1110, 0)).unwrap();
+        assert_eq!(rom.trainer.unwrap().len(), TRAINER_SIZE);
+        assert!(rom.prg_rom.iter().all(|byte| *byte == 0xAA));
+        assert!(rom.chr_rom.is_empty());
+        assert_eq!(rom.mirroring, Mirroring::FourScreen);
+        assert!(rom.battery);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(Rom::new(b"not a rom").is_err());
+
+        let mut truncated = ines(2, 1, 0, 0);
+        truncated.truncate(0x5000);
+        let err = Rom::new(&truncated).unwrap_err();
+        assert!(matches!(err, EmuError::InvalidRom(reason) if reason.starts_with("truncated")));
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let err = Rom::load(Path::new("/nonexistent/game.nes")).unwrap_err();
+        assert!(matches!(err, EmuError::Io(_)));
+    }
+}
@@ -0,0 +1,193 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::CPU_CLOCK_HZ;
+
+/// Where a cartridge clock gets its time from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ClockSource {
+    /// Follows the host's wall clock, shifted by whatever the game set it to
+    #[default]
+    Host,
+    /// Only advances with emulated CPU cycles, so replays and TAS runs see
+    /// the same time on every playback
+    Emulated,
+    /// Doesn't move at all
+    Frozen,
+}
+
+/// How a host backed clock treats the time stored in a savestate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DriftPolicy {
+    /// Keep the game's offset from the host clock, so the clock shows real
+    /// time no matter how old the state is
+    #[default]
+    Resync,
+    /// Continue from the stored time, as if no time passed while the state
+    /// sat on disk
+    Restore,
+}
+
+/// The clock's part of a savestate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RtcState {
+    pub seconds: u64,
+    pub offset: i64,
+    pub cycles: u64,
+}
+
+/// A clock split into the fields cartridge RTC chips expose
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcTime {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub days: u64,
+}
+
+/// Real-time clock for cartridges that have one. Counts seconds since the
+/// Unix epoch; mappers convert that into whatever their chip reports.
+#[derive(Debug, Clone, Default)]
+pub struct Rtc {
+    source: ClockSource,
+    pub drift_policy: DriftPolicy,
+    /// Seconds added to the host clock in [`ClockSource::Host`] mode
+    offset: i64,
+    /// The time in the emulated and frozen modes
+    seconds: u64,
+    /// Progress towards the next emulated second
+    cycles: u64,
+}
+
+impl Rtc {
+    pub fn new(source: ClockSource) -> Self {
+        Self {
+            source,
+            seconds: host_seconds(),
+            ..Default::default()
+        }
+    }
+
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    /// Switches the time source, carrying on from the current time
+    pub fn set_source(&mut self, source: ClockSource) {
+        let now = self.now();
+        self.source = source;
+        self.set(now);
+    }
+
+    pub fn now(&self) -> u64 {
+        match self.source {
+            ClockSource::Host => host_seconds().saturating_add_signed(self.offset),
+            ClockSource::Emulated | ClockSource::Frozen => self.seconds,
+        }
+    }
+
+    pub fn time(&self) -> RtcTime {
+        let now = self.now();
+        RtcTime {
+            seconds: (now % 60) as u8,
+            minutes: (now / 60 % 60) as u8,
+            hours: (now / 3600 % 24) as u8,
+            days: now / 86400,
+        }
+    }
+
+    /// A game setting the clock
+    pub fn set(&mut self, seconds: u64) {
+        self.offset = seconds as i64 - host_seconds() as i64;
+        self.seconds = seconds;
+    }
+
+    /// Advances the emulated clock, see [`ClockSource::Emulated`]
+    pub fn tick(&mut self, cycles: u16) {
+        if self.source != ClockSource::Emulated {
+            return;
+        }
+        self.cycles += cycles as u64;
+        if self.cycles >= CPU_CLOCK_HZ as u64 {
+            self.cycles -= CPU_CLOCK_HZ as u64;
+            self.seconds += 1;
+        }
+    }
+
+    pub fn save(&self) -> RtcState {
+        RtcState {
+            seconds: self.now(),
+            offset: self.offset,
+            cycles: self.cycles,
+        }
+    }
+
+    /// Restores a clock saved with [`Rtc::save`]. Emulated and frozen clocks
+    /// always restore the exact time to stay deterministic.
+    pub fn load(&mut self, state: &RtcState) {
+        self.cycles = state.cycles;
+        match (self.source, self.drift_policy) {
+            (ClockSource::Host, DriftPolicy::Resync) => {
+                self.offset = state.offset;
+                self.seconds = state.seconds;
+            }
+            _ => self.set(state.seconds),
+        }
+    }
+}
+
+fn host_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_emulated_clock_follows_cycles() {
+        let mut rtc = Rtc::new(ClockSource::Emulated);
+        rtc.set(86400 + 3600 + 61);
+        let state = rtc.save();
+
+        for _ in 0..(CPU_CLOCK_HZ / 7 + 1) {
+            rtc.tick(7);
+        }
+        assert_eq!(
+            rtc.time(),
+            RtcTime {
+                seconds: 2,
+                minutes: 1,
+                hours: 1,
+                days: 1
+            }
+        );
+
+        rtc.load(&state);
+        assert_eq!(rtc.now(), 86400 + 3600 + 61);
+
+        rtc.set_source(ClockSource::Frozen);
+        rtc.tick(255);
+        assert_eq!(rtc.now(), state.seconds);
+    }
+
+    #[test]
+    fn test_host_clock_drift_policies() {
+        let mut rtc = Rtc::new(ClockSource::Host);
+        // A game that set its clock an hour ahead
+        rtc.set(host_seconds() + 3600);
+        let mut state = rtc.save();
+        // ...and a state that has been sitting around for a day
+        state.seconds -= 86400;
+
+        rtc.load(&state);
+        assert!(rtc.now().abs_diff(host_seconds() + 3600) <= 1);
+
+        rtc.drift_policy = DriftPolicy::Restore;
+        rtc.load(&state);
+        assert!(rtc.now().abs_diff(state.seconds) <= 1);
+    }
+}
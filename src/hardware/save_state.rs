@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{Apu, CPU, CpuStatus, EmuError, PpuState, RtcState};
+
+/// Start of every savestate file
+const MAGIC: [u8; 4] = *b"NESS";
+/// Bumped whenever the layout below changes. States with any other version
+/// are rejected instead of being misread.
+pub const SAVE_STATE_VERSION: u32 = 5;
+const HEADER_SIZE: usize = MAGIC.len() + 4;
+
+/// Generic over the APU so saving can borrow it while loading gets an owned
+/// one, both encode the same
+#[derive(Serialize, Deserialize)]
+struct SaveState<A> {
+    register_a: u8,
+    register_x: u8,
+    register_y: u8,
+    status: u8,
+    program_counter: u16,
+    stack_pointer: u8,
+    cycles: u64,
+    ram: Vec<u8>,
+    /// Leftover fraction of a PPU dot, so PAL resumes on the same dot
+    dot_remainder: usize,
+    ppu: PpuState,
+    apu: A,
+    mapper: Vec<u8>,
+    /// Kept out of the mapper's own state so every board with a clock gets
+    /// it saved, see [`Rtc::load`](crate::hardware::Rtc::load)
+    rtc: Option<RtcState>,
+}
+
+impl CPU {
+    /// The whole machine as bytes: the magic `NESS`, [`SAVE_STATE_VERSION`]
+    /// as a little endian `u32` and the bincode encoded state. Controllers
+    /// and cheats are left out, they belong to the player rather than the
+    /// game.
+    pub fn save_state(&self) -> Vec<u8> {
+        let rtc = self
+            .bus
+            .cartridge()
+            .borrow_mut()
+            .rtc_mut()
+            .map(|rtc| rtc.save());
+        let state = SaveState {
+            register_a: self.register_a,
+            register_x: self.register_x,
+            register_y: self.register_y,
+            status: self.status.bits(),
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            cycles: self.cycles,
+            ram: self.bus.ram().to_vec(),
+            dot_remainder: self.bus.dot_remainder(),
+            ppu: self.bus.ppu.save_state(),
+            apu: &self.bus.apu,
+            mapper: self.bus.cartridge().borrow().save_state(),
+            rtc,
+        };
+        let mut bytes = Vec::from(MAGIC);
+        bytes.extend(SAVE_STATE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, &state).expect("savestate always serializes");
+        bytes
+    }
+
+    /// Restores a state from [`CPU::save_state`] for the cartridge that's
+    /// inserted. Nothing changes if the state can't be loaded.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), EmuError> {
+        if bytes.len() < HEADER_SIZE || bytes[..MAGIC.len()] != MAGIC {
+            return Err(EmuError::InvalidSaveState("not a savestate".to_string()));
+        }
+        let version = u32::from_le_bytes(bytes[MAGIC.len()..HEADER_SIZE].try_into().unwrap());
+        if version != SAVE_STATE_VERSION {
+            return Err(EmuError::InvalidSaveState(format!(
+                "saved with format version {version}, this build reads {SAVE_STATE_VERSION}"
+            )));
+        }
+        let state: SaveState<Apu> = bincode::deserialize(&bytes[HEADER_SIZE..])
+            .map_err(|err| EmuError::InvalidSaveState(err.to_string()))?;
+        if state.ram.len() != self.bus.ram().len() {
+            return Err(EmuError::InvalidSaveState(
+                "RAM has the wrong size".to_string(),
+            ));
+        }
+
+        // The fallible parts first, so a failure leaves everything as it was
+        let previous_ppu = self.bus.ppu.save_state();
+        self.bus.ppu.load_state(&state.ppu)?;
+        let mapper = self.bus.cartridge().borrow_mut().load_state(&state.mapper);
+        if let Err(err) = mapper {
+            self.bus
+                .ppu
+                .load_state(&previous_ppu)
+                .expect("state saved just now");
+            return Err(err);
+        }
+
+        self.register_a = state.register_a;
+        self.register_x = state.register_x;
+        self.register_y = state.register_y;
+        self.status = CpuStatus::from_bits_truncate(state.status);
+        self.program_counter = state.program_counter;
+        self.stack_pointer = state.stack_pointer;
+        self.cycles = state.cycles;
+        self.last_instruction = None;
+        self.bus.ram_mut().copy_from_slice(&state.ram);
+        self.bus.set_dot_remainder(state.dot_remainder);
+        self.bus.apu.restore(state.apu);
+        if let Some(saved) = &state.rtc
+            && let Some(rtc) = self.bus.cartridge().borrow_mut().rtc_mut()
+        {
+            rtc.load(saved);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::hardware::{ClockSource, Mapper, Mirroring, Region, Rtc};
+
+    use super::*;
+
+    /// A board with nothing but a clock, which leaves it out of its own state
+    struct ClockCartridge {
+        rtc: Rtc,
+    }
+
+    impl Mapper for ClockCartridge {
+        fn cpu_peek(&self, _addr: u16) -> u8 {
+            0
+        }
+
+        fn cpu_write(&mut self, _addr: u16, _data: u8) {}
+
+        fn ppu_read(&self, _addr: u16) -> u8 {
+            0
+        }
+
+        fn ppu_write(&mut self, _addr: u16, _data: u8) {}
+
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Vertical
+        }
+
+        fn save_state(&self) -> Vec<u8> {
+            vec![]
+        }
+
+        fn load_state(&mut self, _state: &[u8]) -> Result<(), EmuError> {
+            Ok(())
+        }
+
+        fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+            Some(&mut self.rtc)
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut cpu = CPU::default();
+        // PAL's 3.2 dots per cycle leave part of a dot over between cycles
+        cpu.bus.set_region(Region::Pal);
+        // LDX #$00; loop: INX; STX $10; JMP loop
+        cpu.load(&[0xa2, 0x00, 0xe8, 0x86, 0x10, 0x4c, 0x02, 0x06])
+            .unwrap();
+        cpu.reset();
+        cpu.run_until_cycles(1000).unwrap();
+        // CHR RAM is part of the state, CHR ROM isn't
+        cpu.bus.ppu.write_register(6, 0x00);
+        cpu.bus.ppu.write_register(6, 0x10);
+        cpu.bus.ppu.write_register(7, 0x5A);
+        let state = cpu.save_state();
+        let (x, pc, cycles) = (cpu.register_x, cpu.program_counter, cpu.cycles);
+        let dot_remainder = cpu.bus.dot_remainder();
+        assert_ne!(dot_remainder, 0);
+
+        cpu.run_until_cycles(5000).unwrap();
+        cpu.bus.ppu.write_register(6, 0x00);
+        cpu.bus.ppu.write_register(6, 0x10);
+        cpu.bus.ppu.write_register(7, 0x00);
+        assert_ne!(cpu.register_x, x);
+
+        cpu.load_state(&state).unwrap();
+        assert_eq!(
+            (cpu.register_x, cpu.program_counter, cpu.cycles),
+            (x, pc, cycles)
+        );
+        assert_eq!(cpu.bus.dot_remainder(), dot_remainder);
+        assert_eq!(cpu.peek(0x10), x);
+        assert_eq!(cpu.bus.cartridge().borrow().ppu_read(0x0010), 0x5A);
+        assert_eq!(cpu.save_state(), state);
+    }
+
+    #[test]
+    fn test_cartridge_clock() {
+        let cartridge = Rc::new(RefCell::new(ClockCartridge {
+            rtc: Rtc::new(ClockSource::Frozen),
+        }));
+        let mut cpu = CPU::default();
+        cpu.bus.insert_cartridge(cartridge.clone());
+        cartridge.borrow_mut().rtc.set(1000);
+        let state = cpu.save_state();
+
+        cartridge.borrow_mut().rtc.set(5000);
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cartridge.borrow().rtc.now(), 1000);
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let mut cpu = CPU::default();
+        let mut state = cpu.save_state();
+        state[4] = state[4].wrapping_add(1);
+        let err = cpu.load_state(&state).unwrap_err();
+        assert!(matches!(err, EmuError::InvalidSaveState(reason) if reason.contains("version")));
+
+        assert!(cpu.load_state(b"NESS").is_err());
+        let truncated = &cpu.save_state()[..100];
+        assert!(cpu.load_state(truncated).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::hardware::{CPU, CpuStatus, EmuError, OpCode};
+
+/// An instruction the CPU has finished executing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutedInstruction {
+    /// Address the opcode was fetched from
+    pub address: u16,
+    pub op_code: OpCode,
+}
+
+/// Read-only copy of the CPU state, handed to inspection hooks so they
+/// can't accidentally modify the machine they're observing
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CpuSnapshot {
+    pub register_a: u8,
+    pub register_x: u8,
+    pub register_y: u8,
+    pub status: CpuStatus,
+    pub program_counter: u16,
+    pub stack_pointer: u8,
+    pub cycles: u64,
+    pub last_instruction: Option<ExecutedInstruction>,
+}
+
+impl CPU {
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            register_a: self.register_a,
+            register_x: self.register_x,
+            register_y: self.register_y,
+            status: self.status,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            cycles: self.cycles,
+            last_instruction: self.last_instruction,
+        }
+    }
+
+    /// Like [`CPU::run_with_callback`] but the hook only gets a snapshot
+    /// of the state after each instruction
+    pub fn run_with_inspector<F>(&mut self, mut inspector: F) -> Result<(), EmuError>
+    where
+        F: FnMut(&CpuSnapshot),
+    {
+        self.run_with_callback(|cpu| inspector(&cpu.snapshot()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hardware::Instruction;
+
+    #[test]
+    fn test_inspector_sees_each_instruction() {
+        let mut cpu = CPU::default();
+        cpu.load(&[0xa9, 0x80, 0xaa, 0x00]).unwrap();
+        cpu.reset();
+
+        let mut snapshots = vec![];
+        cpu.run_with_inspector(|snapshot| snapshots.push(*snapshot))
+            .unwrap();
+
+        assert_eq!(snapshots.len(), 2);
+
+        let lda = snapshots[0];
+        assert_eq!(lda.register_a, 0x80);
+        assert_eq!(lda.program_counter, 0x0602);
+        assert_eq!(lda.status.to_string(), "Nv-bdIzc");
+        let last = lda.last_instruction.unwrap();
+        assert_eq!(last.address, 0x0600);
+        assert!(matches!(last.op_code.instruction, Instruction::LDA));
+
+        let tax = snapshots[1];
+        assert_eq!(tax.register_x, 0x80);
+        assert_eq!(tax.cycles, 4);
+    }
+}
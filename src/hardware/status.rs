@@ -1,3 +1,5 @@
+use std::fmt;
+
 use bitflags::bitflags;
 
 bitflags! {
@@ -45,8 +47,36 @@ impl Default for CpuStatus {
 }
 
 impl CpuStatus {
+    #[inline]
     pub fn update_zero_and_negative_flags(&mut self, value: u8) {
         self.set(CpuStatus::ZERO, value == 0);
         self.set(CpuStatus::NEGATIVE, value & 0b1000_0000 != 0);
     }
 }
+
+/// Shows every flag as its letter, upper case when set, e.g. `Nv-bdIzC`
+impl fmt::Display for CpuStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const FLAGS: [(CpuStatus, char); 7] = [
+            (CpuStatus::NEGATIVE, 'n'),
+            (CpuStatus::OVERFLOW, 'v'),
+            (CpuStatus::BREAK, 'b'),
+            (CpuStatus::DECIMAL_MODE, 'd'),
+            (CpuStatus::INTERRUPT, 'i'),
+            (CpuStatus::ZERO, 'z'),
+            (CpuStatus::CARRY, 'c'),
+        ];
+        for (idx, (flag, letter)) in FLAGS.into_iter().enumerate() {
+            // Bit 5 is unused
+            if idx == 2 {
+                write!(f, "-")?;
+            }
+            if self.contains(flag) {
+                write!(f, "{}", letter.to_ascii_uppercase())?;
+            } else {
+                write!(f, "{letter}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::Write;
+
+use crate::hardware::{AddressingMode, CPU, CPU_OP_CODES, Instruction, OpCode};
+
+/// Formats the instruction at the program counter, before it executes, the
+/// same way as the canonical nestest log so the two can be diffed:
+///
+/// ```text
+/// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+/// ```
+///
+/// Only side-effect free reads are used, so tracing never disturbs the
+/// machine.
+pub fn trace(cpu: &CPU) -> String {
+    let pc = cpu.program_counter;
+    let code = cpu.peek(pc);
+    let op_code = CPU_OP_CODES.get(code);
+    let len = op_code.map_or(1, |op_code| op_code.len.max(1));
+
+    let mut line = format!("{pc:04X}  ");
+    let mut bytes = String::with_capacity(8);
+    for i in 0..len as u16 {
+        if i > 0 {
+            bytes.push(' ');
+        }
+        let _ = write!(bytes, "{:02X}", cpu.peek(pc.wrapping_add(i)));
+    }
+    let _ = write!(line, "{bytes:8} ");
+
+    match op_code {
+        Some(op_code) => {
+            let mnemonic = format!("{:?}", op_code.instruction);
+            let marker = if op_code.is_unofficial() { "*" } else { " " };
+            let _ = write!(line, "{marker}{mnemonic} {}", operand(cpu, &op_code));
+        }
+        None => line.push_str(" ???"),
+    }
+
+    let ppu = &cpu.bus.ppu;
+    format!(
+        "{:47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        line.trim_end(),
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        // The unused bit 5 always reads back as set
+        cpu.status.bits() | 0b0010_0000,
+        cpu.stack_pointer,
+        ppu.scanline(),
+        ppu.dot(),
+        cpu.cycles,
+    )
+}
+
+/// The operand as nestest writes it, with the resolved address and the value
+/// currently stored there
+fn operand(cpu: &CPU, op_code: &OpCode) -> String {
+    use Instruction::*;
+
+    let arg = cpu.program_counter.wrapping_add(1);
+    let byte = cpu.peek(arg);
+    let word = u16::from_le_bytes([byte, cpu.peek(arg.wrapping_add(1))]);
+    let read_u16_zero_page =
+        |ptr: u8| u16::from_le_bytes([cpu.peek(ptr as u16), cpu.peek(ptr.wrapping_add(1) as u16)]);
+
+    match op_code.addressing_mode {
+        AddressingMode::Immediate => format!("#${byte:02X}"),
+        AddressingMode::ZeroPage => format!("${byte:02X} = {:02X}", cpu.peek(byte as u16)),
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+            let (register, index) = match op_code.addressing_mode {
+                AddressingMode::ZeroPageX => ('X', cpu.register_x),
+                _ => ('Y', cpu.register_y),
+            };
+            let addr = byte.wrapping_add(index);
+            format!(
+                "${byte:02X},{register} @ {addr:02X} = {:02X}",
+                cpu.peek(addr as u16)
+            )
+        }
+        AddressingMode::Absolute => match op_code.instruction {
+            JMP | JSR => format!("${word:04X}"),
+            _ => format!("${word:04X} = {:02X}", cpu.peek(word)),
+        },
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+            let (register, index) = match op_code.addressing_mode {
+                AddressingMode::AbsoluteX => ('X', cpu.register_x),
+                _ => ('Y', cpu.register_y),
+            };
+            let addr = word.wrapping_add(index as u16);
+            format!(
+                "${word:04X},{register} @ {addr:04X} = {:02X}",
+                cpu.peek(addr)
+            )
+        }
+        AddressingMode::IndirectX => {
+            let ptr = byte.wrapping_add(cpu.register_x);
+            let addr = read_u16_zero_page(ptr);
+            format!(
+                "(${byte:02X},X) @ {ptr:02X} = {addr:04X} = {:02X}",
+                cpu.peek(addr)
+            )
+        }
+        AddressingMode::IndirectY => {
+            let base = read_u16_zero_page(byte);
+            let addr = base.wrapping_add(cpu.register_y as u16);
+            format!(
+                "(${byte:02X}),Y = {base:04X} @ {addr:04X} = {:02X}",
+                cpu.peek(addr)
+            )
+        }
+        AddressingMode::Other => match op_code.instruction {
+            BCC | BCS | BEQ | BMI | BNE | BPL | BVC | BVS => {
+                let target = arg.wrapping_add(1).wrapping_add(byte as i8 as u16);
+                format!("${target:04X}")
+            }
+            JMP => {
+                // Reproduces the page wrap bug of the real indirect JMP
+                let hi_addr = (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF);
+                let target = u16::from_le_bytes([cpu.peek(word), cpu.peek(hi_addr)]);
+                format!("(${word:04X}) = {target:04X}")
+            }
+            ASL | LSR | ROL | ROR => "A".to_string(),
+            _ => String::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_trace_format() {
+        let mut cpu = CPU::default();
+        // LDA #$05; STA $10; LDX #$01; LDA $0F,X; *NOP $10; BNE +0; BRK
+        cpu.load(&[
+            0xa9, 0x05, 0x85, 0x10, 0xa2, 0x01, 0xb5, 0x0f, 0x04, 0x10, 0xd0, 0x00, 0x00,
+        ])
+        .unwrap();
+        cpu.reset();
+
+        let mut lines = vec![trace(&cpu)];
+        cpu.run_with_callback(|cpu| lines.push(trace(cpu))).unwrap();
+
+        assert_eq!(
+            lines[0],
+            "0600  A9 05     LDA #$05                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:0"
+        );
+        assert!(lines[1].starts_with("0602  85 10     STA $10 = 00  "));
+        assert!(lines[3].starts_with("0606  B5 0F     LDA $0F,X @ 10 = 05  "));
+        assert!(lines[4].starts_with("0608  04 10    *NOP $10 = 05  "));
+        assert!(lines[5].starts_with("060A  D0 00     BNE $060C  "));
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::{VecDeque, vec_deque::Drain},
+    ops::RangeInclusive,
+};
+
+/// Changes kept for a host that doesn't drain them, the oldest are dropped
+/// beyond this
+const MAX_CHANGES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchId(usize);
+
+/// When a write to a watched address should be reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchCondition {
+    /// The write changed the stored value
+    Changed,
+    /// The new value is greater than the old one
+    Increased,
+    /// The new value is less than the old one
+    Decreased,
+    /// Any write of a value greater than or equal to the given one
+    WrittenAtLeast(u8),
+    /// Any write of a value less than the given one
+    WrittenBelow(u8),
+    /// Any write of exactly the given value
+    WrittenEqual(u8),
+}
+
+impl WatchCondition {
+    #[inline]
+    fn matches(&self, old: u8, new: u8) -> bool {
+        match *self {
+            WatchCondition::Changed => old != new,
+            WatchCondition::Increased => new > old,
+            WatchCondition::Decreased => new < old,
+            WatchCondition::WrittenAtLeast(value) => new >= value,
+            WatchCondition::WrittenBelow(value) => new < value,
+            WatchCondition::WrittenEqual(value) => new == value,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Watch {
+    id: WatchId,
+    range: RangeInclusive<u16>,
+    condition: WatchCondition,
+    /// Stop execution instead of only queueing the change
+    breakpoint: bool,
+}
+
+/// A write to a watched address that met the watch's condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub watch: WatchId,
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+    /// Emulated frame the write happened in, see
+    /// [`FrameCounter::frames`](crate::hardware::FrameCounter::frames)
+    pub frame: u64,
+}
+
+/// Subscriptions to changes in memory ranges, used by HUD scripts and
+/// frontends that want to react to values like score or lives without
+/// polling memory after every instruction. Only internal RAM and the
+/// cartridge RAM at `$6000..=$7FFF` can be watched, since other addresses
+/// don't store what's written to them.
+#[derive(Debug, Default)]
+pub struct MemoryWatches {
+    next_id: usize,
+    watches: Vec<Watch>,
+    changes: VecDeque<MemoryChange>,
+    hit: Option<MemoryChange>,
+}
+
+impl MemoryWatches {
+    /// Reports every change to a value in `range`
+    pub fn add(&mut self, range: RangeInclusive<u16>) -> WatchId {
+        self.add_with_condition(range, WatchCondition::Changed)
+    }
+
+    pub fn add_with_condition(
+        &mut self,
+        range: RangeInclusive<u16>,
+        condition: WatchCondition,
+    ) -> WatchId {
+        self.push(range, condition, false)
+    }
+
+    /// Like [`MemoryWatches::add_with_condition`] but also stops
+    /// [`CPU::run`](crate::hardware::CPU::run) after the instruction
+    /// that triggered it, see [`MemoryWatches::hit`]
+    pub fn add_watchpoint(
+        &mut self,
+        range: RangeInclusive<u16>,
+        condition: WatchCondition,
+    ) -> WatchId {
+        self.push(range, condition, true)
+    }
+
+    fn push(
+        &mut self,
+        range: RangeInclusive<u16>,
+        condition: WatchCondition,
+        breakpoint: bool,
+    ) -> WatchId {
+        let id = WatchId(self.next_id);
+        self.next_id += 1;
+        self.watches.push(Watch {
+            id,
+            range,
+            condition,
+            breakpoint,
+        });
+        id
+    }
+
+    /// Returns false if the watch was already removed
+    pub fn remove(&mut self, id: WatchId) -> bool {
+        let len = self.watches.len();
+        self.watches.retain(|watch| watch.id != id);
+        self.watches.len() != len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    /// Whether writes to `addr` are checked, see [`MemoryWatches`]
+    #[inline]
+    pub(crate) fn watches_addr(addr: u16) -> bool {
+        matches!(addr, 0x0000..=0x1FFF | 0x6000..=0x7FFF)
+    }
+
+    /// A write of `new` over the stored `old` during `frame`
+    pub(crate) fn check(&mut self, addr: u16, old: u8, new: u8, frame: u64) {
+        for watch in &self.watches {
+            if !watch.range.contains(&addr) || !watch.condition.matches(old, new) {
+                continue;
+            }
+            let change = MemoryChange {
+                watch: watch.id,
+                addr,
+                old,
+                new,
+                frame,
+            };
+            if self.changes.len() == MAX_CHANGES {
+                self.changes.pop_front();
+            }
+            self.changes.push_back(change);
+            if watch.breakpoint && self.hit.is_none() {
+                self.hit = Some(change);
+            }
+        }
+    }
+
+    /// The watchpoint write that stopped the last run, if any
+    pub fn hit(&self) -> Option<MemoryChange> {
+        self.hit
+    }
+
+    pub(crate) fn clear_hit(&mut self) {
+        self.hit = None;
+    }
+
+    /// Hands out every change recorded since the last drain, oldest first.
+    /// Frontends call this once per frame; beyond 4096 undrained changes
+    /// the oldest are dropped.
+    pub fn drain(&mut self) -> Drain<'_, MemoryChange> {
+        self.changes.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hardware::{CPU, Cheat};
+
+    #[test]
+    fn test_changes_are_batched() {
+        let mut cpu = CPU::default();
+        let lives = cpu.watches.add(0x0700..=0x07FF);
+
+        cpu.mem_write(0x0750, 3);
+        // Writing the same value again isn't a change
+        cpu.mem_write(0x0750, 3);
+        // Outside of the watched range
+        cpu.mem_write(0x0800, 1);
+
+        let changes: Vec<_> = cpu.watches.drain().collect();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].watch, lives);
+        assert_eq!(
+            (changes[0].addr, changes[0].old, changes[0].new),
+            (0x0750, 0, 3)
+        );
+        assert_eq!(changes[0].frame, 0);
+
+        // Stamped with the emulated frame, however often it's drained
+        while !cpu.bus.tick(100) {}
+        assert_eq!(cpu.watches.drain().count(), 0);
+        cpu.mem_write(0x0750, 2);
+        let changes: Vec<_> = cpu.watches.drain().collect();
+        assert_eq!(changes[0].frame, 1);
+        assert_eq!(changes[0].old, 3);
+    }
+
+    #[test]
+    fn test_undrained_changes_are_capped() {
+        let mut cpu = CPU::default();
+        cpu.watches
+            .add_with_condition(0x10..=0x10, WatchCondition::WrittenAtLeast(0));
+        for value in 0..MAX_CHANGES + 10 {
+            cpu.mem_write(0x10, value as u8);
+        }
+        let changes: Vec<_> = cpu.watches.drain().collect();
+        assert_eq!(changes.len(), MAX_CHANGES);
+        assert_eq!(changes[0].new, 10);
+    }
+
+    #[test]
+    fn test_old_value_is_stored_value() {
+        let mut cpu = CPU::default();
+        cpu.watches.add(0x0000..=0xFFFF);
+        cpu.bus.cheats.add(Cheat::Replace {
+            address: 0x10,
+            value: 0x99,
+            compare: None,
+        });
+
+        cpu.mem_write(0x10, 1);
+        // Registers don't hold what's written to them
+        cpu.mem_write(0x2000, 0x80);
+        let changes: Vec<_> = cpu.watches.drain().map(|c| (c.addr, c.old)).collect();
+        assert_eq!(changes, [(0x10, 0)]);
+    }
+
+    #[test]
+    fn test_removed_watch_stops_reporting() {
+        let mut cpu = CPU::default();
+        let id = cpu.watches.add(0x10..=0x10);
+        assert!(cpu.watches.remove(id));
+        assert!(!cpu.watches.remove(id));
+
+        cpu.mem_write(0x10, 1);
+        assert_eq!(cpu.watches.drain().count(), 0);
+    }
+
+    #[test]
+    fn test_value_conditions() {
+        let mut cpu = CPU::default();
+        let decreased = cpu
+            .watches
+            .add_with_condition(0x0756..=0x0756, WatchCondition::Decreased);
+        let high = cpu
+            .watches
+            .add_with_condition(0x0300..=0x03FF, WatchCondition::WrittenAtLeast(0x80));
+
+        cpu.mem_write(0x0756, 3);
+        cpu.mem_write(0x0756, 2);
+        cpu.mem_write(0x0300, 0x7F);
+        cpu.mem_write(0x0300, 0x80);
+        // Counts even though the value didn't change
+        cpu.mem_write(0x0300, 0x80);
+
+        let changes: Vec<_> = cpu.watches.drain().map(|c| (c.watch, c.new)).collect();
+        assert_eq!(changes, [(decreased, 2), (high, 0x80), (high, 0x80)]);
+    }
+
+    #[test]
+    fn test_watchpoint_stops_run() {
+        let mut cpu = CPU::default();
+        cpu.watches
+            .add_watchpoint(0x10..=0x10, WatchCondition::Decreased);
+        // LDA #$05, STA $10, DEC $10, LDA #$01, BRK
+        cpu.load(&[0xa9, 0x05, 0x85, 0x10, 0xc6, 0x10, 0xa9, 0x01, 0x00])
+            .unwrap();
+        cpu.reset();
+        cpu.run().unwrap();
+
+        let hit = cpu.watches.hit().unwrap();
+        assert_eq!((hit.old, hit.new), (5, 4));
+        // Stopped right after the DEC
+        assert_eq!(cpu.program_counter, 0x0606);
+        assert_eq!(cpu.register_a, 0x05);
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use crate::hardware::{Button, InputEvent};
+
+const BUTTONS: [Button; 9] = [
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+    Button::Trigger,
+];
+
+/// Scripted button presses for automated runs, one `frame:buttons` line per
+/// change, e.g.
+///
+/// ```text
+/// # wait for the title screen then press start
+/// 60:start
+/// 62:
+/// 120:right,a
+/// ```
+///
+/// The buttons on a line are held from that frame until the next line; an
+/// empty list releases everything.
+#[derive(Debug, Default)]
+pub struct InputScript {
+    steps: Vec<(u64, Vec<Button>)>,
+    next: usize,
+    held: Vec<Button>,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("reading input script {}", path.display()))?;
+        Self::parse(&source).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut steps: Vec<(u64, Vec<Button>)> = vec![];
+        for (idx, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((frame, buttons)) = line.split_once(':') else {
+                bail!("line {}: expected frame:buttons", idx + 1);
+            };
+            let frame: u64 = frame
+                .trim()
+                .parse()
+                .with_context(|| format!("line {}: invalid frame {frame:?}", idx + 1))?;
+            if let Some((last, _)) = steps.last()
+                && *last >= frame
+            {
+                bail!("line {}: frame {frame} is not after frame {last}", idx + 1);
+            }
+
+            let buttons = buttons
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| match button_from_name(name) {
+                    Some(button) => Ok(button),
+                    None => bail!("line {}: unknown button {name:?}", idx + 1),
+                })
+                .collect::<Result<_>>()?;
+            steps.push((frame, buttons));
+        }
+
+        Ok(Self {
+            steps,
+            ..Default::default()
+        })
+    }
+
+    /// Whether every line has been played back
+    pub fn finished(&self) -> bool {
+        self.next == self.steps.len()
+    }
+
+    /// Calls `apply` with the presses and releases that take effect at
+    /// `frame`. Frames must be passed in increasing order.
+    pub fn advance(&mut self, frame: u64, mut apply: impl FnMut(InputEvent)) {
+        while let Some((at, buttons)) = self.steps.get(self.next)
+            && *at <= frame
+        {
+            for button in BUTTONS {
+                let was = self.held.contains(&button);
+                let now = buttons.contains(&button);
+                if was != now {
+                    apply(InputEvent::Button {
+                        button,
+                        pressed: now,
+                    });
+                }
+            }
+            self.held.clone_from(buttons);
+            self.next += 1;
+        }
+    }
+}
+
+fn button_from_name(name: &str) -> Option<Button> {
+    let button = match name.to_ascii_lowercase().as_str() {
+        "a" => Button::A,
+        "b" => Button::B,
+        "select" => Button::Select,
+        "start" => Button::Start,
+        "up" => Button::Up,
+        "down" => Button::Down,
+        "left" => Button::Left,
+        "right" => Button::Right,
+        "trigger" => Button::Trigger,
+        _ => return None,
+    };
+    Some(button)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn events(script: &mut InputScript, frame: u64) -> Vec<InputEvent> {
+        let mut events = vec![];
+        script.advance(frame, |event| events.push(event));
+        events
+    }
+
+    #[test]
+    fn test_playback() {
+        let mut script = InputScript::parse("# menu\n10:start\n12:\n20: Right, A\n").unwrap();
+
+        assert!(events(&mut script, 9).is_empty());
+        assert_eq!(
+            events(&mut script, 10),
+            [InputEvent::Button {
+                button: Button::Start,
+                pressed: true
+            }]
+        );
+        assert_eq!(
+            events(&mut script, 12),
+            [InputEvent::Button {
+                button: Button::Start,
+                pressed: false
+            }]
+        );
+        // Skipped frames still apply every step in between
+        assert_eq!(events(&mut script, 30).len(), 2);
+        assert!(script.finished());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = InputScript::parse("5:a\n3:b").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: frame 3 is not after frame 5");
+        assert!(InputScript::parse("5:jump").is_err());
+        assert!(InputScript::parse("start").is_err());
+    }
+}
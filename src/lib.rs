@@ -1,2 +1,11 @@
 pub mod app;
+pub mod cheat_file;
+pub mod frame;
+pub mod frame_stats;
 pub mod hardware;
+pub mod input_script;
+pub mod pacer;
+pub mod paths;
+pub mod sav_file;
+pub mod scaler;
+pub mod screenshot;
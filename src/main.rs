@@ -1,12 +1,34 @@
-use nes_emu_rs::hardware::{CPU, Gamepad};
+use nes_emu_rs::{
+    app::IdleState,
+    cheat_file::CheatFile,
+    frame::Frame,
+    frame_stats::{FrameStats, FrameTiming},
+    hardware::{
+        Button, CPU, CallGraph, ControllerPort, EmuError, InputEvent, Region, Rom, SAMPLE_RATE,
+        SCREEN_HEIGHT, SCREEN_WIDTH, SnakeKeypad, trace,
+    },
+    input_script::InputScript,
+    pacer::SpeedControl,
+    paths::{DataDirs, DataKind},
+    sav_file::SavFile,
+    scaler::Scaler,
+    screenshot::{self, ScreenshotKind},
+};
 use rand::Rng;
 use sdl2::{
     EventPump,
-    event::Event,
-    keyboard::Keycode,
+    audio::{AudioQueue, AudioSpecDesired},
+    event::{Event, WindowEvent},
+    keyboard::{Keycode, Mod},
     pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::{Duration, Instant},
 };
-use std::sync::LazyLock;
 
 static SNAKE_CODE: LazyLock<Vec<u8>> = LazyLock::new(|| {
     vec![
@@ -35,76 +57,605 @@ static SNAKE_CODE: LazyLock<Vec<u8>> = LazyLock::new(|| {
 });
 
 fn main() {
+    let data_dirs = DataDirs::resolve(data_root().as_deref());
+    if let Err(err) = prepare_data_dirs(&data_dirs) {
+        eprintln!("warning: could not set up data directories: {err}");
+    }
+
+    // `--rom <path>` runs an iNES cartridge, without one the built-in snake
+    let rom_path = arg_value("--rom").map(PathBuf::from);
+    let snake = rom_path.is_none();
+    // `--headless` runs the cartridge without a window, see `run_headless`
+    let headless = std::env::args().any(|arg| arg == "--headless");
+    if headless && snake {
+        eprintln!("error: --headless needs a cartridge to run, pass one with --rom");
+        std::process::exit(1);
+    }
+    // Names screenshots and savestates
+    let game = rom_path
+        .as_deref()
+        .and_then(Path::file_stem)
+        .map_or("snake".to_string(), |stem| {
+            stem.to_string_lossy().into_owned()
+        });
+    let window_title = if snake { "Snake Game" } else { game.as_str() };
+    // Snake draws its 32x32 grid of cells from RAM, cartridges the PPU
+    let (width, height, window_scale) = if snake {
+        (32, 32, 10)
+    } else {
+        (SCREEN_WIDTH, SCREEN_HEIGHT, 3)
+    };
+
+    let mut cpu = CPU::default();
+    if let Some(name) = arg_value("--region") {
+        match Region::from_name(&name) {
+            Some(region) => cpu.bus.set_region(region),
+            None => eprintln!("warning: unknown region {name:?}, using ntsc"),
+        }
+    }
+    match &rom_path {
+        Some(path) => {
+            let loaded = Rom::load(path).and_then(|rom| cpu.load_rom(&rom));
+            if let Err(err) = loaded {
+                eprintln!("error: loading {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        }
+        None => cpu.load(&SNAKE_CODE).expect("snake fits in RAM"),
+    }
+    // Battery backed RAM, kept in a `.sav` next to the ROM. Headless runs
+    // leave it alone so they play out the same every time.
+    let mut sav = match &rom_path {
+        Some(path) if !headless => {
+            SavFile::open(path, &mut cpu, Instant::now()).unwrap_or_else(|err| {
+                // Not saving rather than overwriting a save we couldn't read
+                eprintln!("warning: could not load save, progress won't be kept: {err:#}");
+                None
+            })
+        }
+        _ => None,
+    };
+    let cheat_path = CheatFile::path(&data_dirs, &game);
+    match CheatFile::load(&cheat_path) {
+        Ok(cheats) => {
+            cheats.apply(&mut cpu.bus.cheats);
+        }
+        Err(err) => eprintln!("warning: could not load cheats: {err:#}"),
+    }
+    cpu.reset();
+
+    let mut script = match arg_value("--inputs") {
+        Some(path) => match InputScript::load(path.as_ref()) {
+            Ok(script) => Some(script),
+            Err(err) => {
+                eprintln!("error: {err:#}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let dump = dump_frames();
+    if arg_value("--call-graph").is_some() {
+        cpu.call_graph = CallGraph::enabled();
+    }
+
+    if headless {
+        let run_frames = match arg_value("--run-frames").map(|n| n.parse::<u64>()) {
+            Some(Ok(frames)) => Some(frames),
+            Some(Err(_)) => {
+                eprintln!("error: --run-frames expects a number of frames");
+                std::process::exit(1);
+            }
+            None if script.is_some() => None,
+            None => {
+                eprintln!("error: --headless needs --run-frames or --inputs to know when to stop");
+                std::process::exit(1);
+            }
+        };
+        let result = run_headless(&mut cpu, script, dump, run_frames);
+        write_call_graph(&cpu);
+        if let Err(err) = result {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("Snake Game", (32.0 * 10.0) as u32, (32.0 * 10.0) as u32)
+        .window(
+            window_title,
+            (width * window_scale) as u32,
+            (height * window_scale) as u32,
+        )
         .position_centered()
         .build()
         .expect("window");
 
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let audio = open_audio(&sdl_context);
+    // Reused between frames, a bit more than a frame's worth of samples
+    let mut audio_buffer = vec![0.0; 1024];
+
+    let scaler = match arg_value("--scaler") {
+        Some(name) => Scaler::from_name(&name).unwrap_or_else(|| {
+            eprintln!("warning: unknown scaler {name:?}, using none");
+            Scaler::None
+        }),
+        None => Scaler::None,
+    };
+    let scale = window_scale as f32 / scaler.factor() as f32;
+    canvas.set_scale(scale, scale).expect("set scale");
 
-    canvas.set_scale(10.0, 10.0).expect("set scale");
+    let mut screen_state = Frame::new(width, height);
+    let mut scaled = scaler.output_frame(&screen_state);
 
     let creator = canvas.texture_creator();
     let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, 32, 32)
+        .create_texture_target(
+            PixelFormatEnum::RGB24,
+            scaled.width() as u32,
+            scaled.height() as u32,
+        )
         .expect("set to valid texture target");
 
-    let mut cpu = CPU::default();
-    cpu.load(&SNAKE_CODE);
-    cpu.reset();
-
-    let mut screen_state = [0_u8; 32 * 3 * 32];
+    let mut keypad = snake.then(SnakeKeypad::default);
+    let mut speed = SpeedControl::default();
+    let mut idle = IdleState::new(std::env::args().any(|arg| arg == "--pause-on-focus-loss"));
+    let mut last_step = Instant::now();
     let mut rng = rand::thread_rng();
+    let mut stats = FrameStats::default();
+    // nestest style log of every instruction on stdout
+    let trace_cpu = std::env::args().any(|arg| arg == "--trace");
+    if trace_cpu {
+        println!("{}", trace(&cpu));
+    }
+    let state_path = data_dirs
+        .dir(DataKind::States)
+        .join(format!("{game}.state"));
+    let mut last_frame = Instant::now();
+    // Cartridges are paced to the console's frame rate
+    let frame_time = Duration::from_secs_f64(1.0 / cpu.bus.region().frame_rate());
+    let mut next_frame = Instant::now();
+    // Reused between title updates to keep the frame loop allocation free
+    let mut title = String::with_capacity(128);
 
-    cpu.run_with_callback(move |cpu| {
-        handle_user_input(cpu, &mut event_pump);
-        cpu.mem_write(0xfe, rng.gen_range(1, 16));
+    let result = loop {
+        let step = match cpu.step() {
+            Ok(step) => step,
+            Err(err) => break Err(err),
+        };
+        if trace_cpu {
+            println!("{}", trace(&cpu));
+        }
+        // Snake ends with BRK once the game is over
+        if snake && step.is_break() {
+            break Ok(());
+        }
+        if step.frame_done {
+            cpu.bus.apply_freeze_cheats();
+            if let Some(sav) = &mut sav
+                && let Err(err) = sav.autosave(&cpu, Instant::now())
+            {
+                eprintln!("warning: could not save: {err:#}");
+            }
+            // Drained every frame so the ring buffer never overflows, even
+            // while nothing on screen changes
+            if let Some(audio) = &audio {
+                let count = cpu.bus.apu.samples.pull(&mut audio_buffer);
+                if let Err(err) = audio.queue_audio(&audio_buffer[..count]) {
+                    eprintln!("warning: could not queue audio: {err}");
+                }
+            }
+        }
+        // Cartridges see the front-end once per frame, snake after every
+        // instruction
+        if !snake && !step.frame_done {
+            continue;
+        }
 
-        if read_screen_state(cpu, &mut screen_state) {
+        match handle_user_input(
+            &mut cpu,
+            &mut keypad,
+            &mut speed,
+            &mut idle,
+            &mut event_pump,
+        ) {
+            Some(Hotkey::Screenshot(kind)) => {
+                let frame = match kind {
+                    ScreenshotKind::Native => &screen_state,
+                    ScreenshotKind::Output => &scaled,
+                };
+                let dir = data_dirs.dir(DataKind::Screenshots);
+                let path = screenshot::screenshot_path(dir, &game, kind);
+                match screenshot::save_png(frame, &path) {
+                    Ok(()) => println!("Saved screenshot {}", path.display()),
+                    Err(err) => eprintln!("warning: could not save screenshot: {err:#}"),
+                }
+            }
+            Some(Hotkey::SaveState) => match std::fs::write(&state_path, cpu.save_state()) {
+                Ok(()) => println!("Saved state {}", state_path.display()),
+                Err(err) => eprintln!("warning: could not save state: {err}"),
+            },
+            Some(Hotkey::LoadState) => {
+                let loaded = std::fs::read(&state_path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|bytes| cpu.load_state(&bytes).map_err(|err| err.to_string()));
+                match loaded {
+                    Ok(()) => println!("Loaded state {}", state_path.display()),
+                    Err(err) => eprintln!("warning: could not load state: {err}"),
+                }
+            }
+            Some(Hotkey::Quit) => break Ok(()),
+            None => {}
+        }
+        // Snake has no PPU, so its frames are the ones presented
+        let frame = if snake {
+            stats.frames()
+        } else {
+            cpu.bus.frame_counter().frames()
+        };
+        if let Some(script) = &mut script {
+            script.advance(frame, |event| {
+                cpu.bus.controllers[0].handle_event(event);
+                if let Some(keypad) = &mut keypad {
+                    keypad.handle_event(event);
+                    cpu.mem_write(SnakeKeypad::ADDRESS, keypad.read());
+                }
+            });
+        }
+        if snake {
+            cpu.mem_write(0xfe, rng.gen_range(1, 16));
+            read_screen_state(&cpu, &mut screen_state);
+        } else {
+            cpu.bus.ppu.render_frame(&mut screen_state);
+        }
+        if screen_state.take_dirty_rows().is_some() {
+            scaler.apply(&screen_state, &mut scaled);
+        }
+        let dirty_rows = scaled.take_dirty_rows();
+        let presented = dirty_rows.is_some();
+        if let Some(rows) = dirty_rows {
+            let render_start = Instant::now();
+            // Only upload the rows that changed since the last frame
+            let region = Rect::new(
+                0,
+                rows.start as i32,
+                scaled.width() as u32,
+                rows.len() as u32,
+            );
             texture
-                .update(None, &screen_state, 32 * 3)
+                .update(region, scaled.rows(rows), scaled.pitch())
                 .expect("updated texture");
             canvas.copy(&texture, None, None).unwrap();
+
+            let present_start = Instant::now();
             canvas.present();
+            let present_end = Instant::now();
+
+            stats.push(FrameTiming {
+                emulation: render_start - last_frame,
+                render: present_start - render_start,
+                present: present_end - present_start,
+            });
+            last_frame = present_end;
+
+            // Updating the title every frame is expensive on some window managers
+            if stats.frames() % 30 == 0 {
+                title.clear();
+                write!(title, "{window_title} {} {stats}", cpu.bus.frame_counter())
+                    .expect("format title");
+                canvas.window_mut().set_title(&title).expect("set title");
+            }
         }
-        ::std::thread::sleep(std::time::Duration::new(0, 70_000));
-    });
+        // Cartridges dump every emulated frame, unchanged or not, so numbers
+        // and `--frames` count the game's frames. Snake only has presented ones.
+        if let Some((dir, every)) = &dump
+            && (presented || !snake)
+            && frame.is_multiple_of(*every)
+        {
+            dump_frame(dir, frame, &screen_state);
+        }
+
+        let now = Instant::now();
+        speed.update(now - last_step);
+        last_step = now;
+        if snake {
+            ::std::thread::sleep(speed.scale(Duration::new(0, 70_000)));
+        } else {
+            next_frame += speed.scale(frame_time);
+            match next_frame.checked_duration_since(now) {
+                Some(wait) => ::std::thread::sleep(wait),
+                // Running behind, don't try to catch up
+                None => next_frame = now,
+            }
+        }
+    };
+    if let Some(sav) = &mut sav {
+        match sav.save(&cpu) {
+            Ok(true) => println!("Saved {}", sav.path().display()),
+            Ok(false) => {}
+            Err(err) => eprintln!("warning: could not save: {err:#}"),
+        }
+    }
+    write_call_graph(&cpu);
+    if let Err(err) = result {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    }
 }
 
-fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
-    for event in event_pump.poll_iter() {
+/// Runs the cartridge as fast as it goes, without a window, audio or frame
+/// pacing, for automated runs driven by `--inputs` and `--dump-frames`.
+/// Stops after `run_frames` frames, or once the script has played out if
+/// that's `None`.
+fn run_headless(
+    cpu: &mut CPU,
+    mut script: Option<InputScript>,
+    dump: Option<(PathBuf, u64)>,
+    run_frames: Option<u64>,
+) -> Result<(), EmuError> {
+    let mut screen = Frame::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    loop {
+        let frames = cpu.bus.frame_counter().frames();
+        let done = match run_frames {
+            Some(run_frames) => frames >= run_frames,
+            None => script.as_ref().is_none_or(InputScript::finished),
+        };
+        if done {
+            return Ok(());
+        }
+
+        cpu.run_frame()?;
+        cpu.bus.apply_freeze_cheats();
+        let frame = cpu.bus.frame_counter().frames();
+        if let Some(script) = &mut script {
+            script.advance(frame, |event| cpu.bus.controllers[0].handle_event(event));
+        }
+        if let Some((dir, every)) = &dump
+            && frame.is_multiple_of(*every)
+        {
+            cpu.bus.ppu.render_frame(&mut screen);
+            dump_frame(dir, frame, &screen);
+        }
+    }
+}
+
+/// Mono output at the APU's sample rate. Runs silently without audio if no
+/// device could be opened.
+fn open_audio(sdl_context: &sdl2::Sdl) -> Option<AudioQueue<f32>> {
+    let desired = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE as i32),
+        channels: Some(1),
+        samples: None,
+    };
+    let queue = sdl_context
+        .audio()
+        .and_then(|audio| audio.open_queue(None, &desired));
+    match queue {
+        Ok(queue) => {
+            queue.resume();
+            Some(queue)
+        }
+        Err(err) => {
+            eprintln!("warning: no audio: {err}");
+            None
+        }
+    }
+}
+
+/// Root for all data files from `--data-dir <path>`, or the folder next to
+/// the executable in portable mode. `None` means the platform directories.
+fn data_root() -> Option<PathBuf> {
+    if let Some(root) = arg_value("--data-dir") {
+        return Some(PathBuf::from(root));
+    }
+
+    let portable = std::env::args().any(|arg| arg == "--portable");
+    let exe = std::env::current_exe().ok()?;
+    DataDirs::portable_root(exe.parent()?, portable)
+}
+
+/// `--dump-frames <dir>` writes every `--frames <n>`th native frame as a
+/// numbered PNG, e.g. for visual regression galleries
+fn dump_frames() -> Option<(PathBuf, u64)> {
+    let dir = PathBuf::from(arg_value("--dump-frames")?);
+    let every = match arg_value("--frames").map(|n| n.parse()) {
+        Some(Ok(every)) if every > 0 => every,
+        Some(_) => {
+            eprintln!("warning: --frames expects a positive number, dumping every frame");
+            1
+        }
+        None => 1,
+    };
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        eprintln!("warning: could not create {}: {err}", dir.display());
+    }
+    Some((dir, every))
+}
+
+/// Writes `frame` as the numbered PNG for frame `number` in `dir`
+fn dump_frame(dir: &Path, number: u64, frame: &Frame) {
+    let path = screenshot::dump_path(dir, number);
+    if let Err(err) = screenshot::save_png(frame, &path) {
+        eprintln!("warning: could not dump frame: {err:#}");
+    }
+}
+
+/// `--call-graph <path>` writes the subroutine call graph on exit, as JSON
+/// for a `.json` path and Graphviz DOT otherwise
+fn write_call_graph(cpu: &CPU) {
+    let Some(path) = arg_value("--call-graph").map(PathBuf::from) else {
+        return;
+    };
+    let contents = if path.extension().is_some_and(|ext| ext == "json") {
+        cpu.call_graph.to_json()
+    } else {
+        cpu.call_graph.to_dot()
+    };
+    match std::fs::write(&path, contents) {
+        Ok(()) => println!("Saved call graph {}", path.display()),
+        Err(err) => eprintln!("warning: could not save call graph: {err}"),
+    }
+}
+
+/// The argument following `flag` on the command line
+fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+    }
+    None
+}
+
+fn prepare_data_dirs(data_dirs: &DataDirs) -> std::io::Result<()> {
+    data_dirs.create_all()?;
+    // Older builds kept everything next to the executable
+    let exe = std::env::current_exe()?;
+    if let Some(legacy) = exe.parent() {
+        for path in data_dirs.migrate_legacy(legacy)? {
+            println!("Migrated {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// A front-end action the main loop carries out
+enum Hotkey {
+    /// F12 for the output frame, Shift+F12 for the native frame
+    Screenshot(ScreenshotKind),
+    /// F5, quick save
+    SaveState,
+    /// F9, quick load
+    LoadState,
+    /// Escape or closing the window
+    Quit,
+}
+
+/// Applies input to the game and returns the last hotkey that was pressed.
+/// Holding Tab fast-forwards and backquote toggles turbo.
+/// Doesn't return while the window is minimized, or unfocused with
+/// `--pause-on-focus-loss`, so nothing is emulated or presented meanwhile.
+fn handle_user_input(
+    cpu: &mut CPU,
+    keypad: &mut Option<SnakeKeypad>,
+    speed: &mut SpeedControl,
+    idle: &mut IdleState,
+    event_pump: &mut EventPump,
+) -> Option<Hotkey> {
+    let mut hotkey = None;
+    loop {
+        let event = match event_pump.poll_event() {
+            Some(event) => event,
+            // While paused, block on the next event instead of emulating
+            None if idle.should_pause() => event_pump.wait_event(),
+            None => break,
+        };
         match event {
+            Event::Window { win_event, .. } => match win_event {
+                WindowEvent::FocusGained => idle.set_focused(true),
+                WindowEvent::FocusLost => idle.set_focused(false),
+                WindowEvent::Minimized => idle.set_minimized(true),
+                WindowEvent::Restored | WindowEvent::Maximized => idle.set_minimized(false),
+                _ => {}
+            },
             Event::Quit { .. }
             | Event::KeyDown {
                 keycode: Some(Keycode::Escape),
                 ..
-            } => std::process::exit(0),
-
-            Event::KeyDown { keycode, .. } => match keycode {
-                Some(Keycode::W) => cpu.set_gamepad_button(Gamepad::UP),
-                Some(Keycode::A) => cpu.set_gamepad_button(Gamepad::LEFT),
-                Some(Keycode::S) => cpu.set_gamepad_button(Gamepad::DOWN),
-                Some(Keycode::D) => cpu.set_gamepad_button(Gamepad::RIGHT),
-                // Some(Keycode::W) => cpu.mem_write(0xff, 0x77),
-                // Some(Keycode::A) => cpu.mem_write(0xff, 0x61),
-                // Some(Keycode::S) => cpu.mem_write(0xff, 0x73),
-                // Some(Keycode::D) => cpu.mem_write(0xff, 0x64),
-                _ => {}
-            },
-            // Event::KeyUp { keycode, .. } => match keycode {
-            //     Some(Keycode::W) => cpu.set_gamepad_button(Gamepad::UP, false),
-            //     Some(Keycode::A) => cpu.set_gamepad_button(Gamepad::LEFT, false),
-            //     Some(Keycode::S) => cpu.set_gamepad_button(Gamepad::DOWN, false),
-            //     Some(Keycode::D) => cpu.set_gamepad_button(Gamepad::RIGHT, false),
-            //     _ => {}
-            // },
+            } => return Some(Hotkey::Quit),
+
+            Event::KeyDown {
+                keycode: Some(Keycode::F12),
+                keymod,
+                ..
+            } => {
+                hotkey = Some(Hotkey::Screenshot(
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        ScreenshotKind::Native
+                    } else {
+                        ScreenshotKind::Output
+                    },
+                ));
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::F5),
+                repeat: false,
+                ..
+            } => hotkey = Some(Hotkey::SaveState),
+            Event::KeyDown {
+                keycode: Some(Keycode::F9),
+                repeat: false,
+                ..
+            } => hotkey = Some(Hotkey::LoadState),
+
+            Event::KeyDown {
+                keycode: Some(Keycode::Tab),
+                ..
+            } => speed.set_held(true),
+            Event::KeyUp {
+                keycode: Some(Keycode::Tab),
+                ..
+            } => speed.set_held(false),
+            Event::KeyDown {
+                keycode: Some(Keycode::Backquote),
+                repeat: false,
+                ..
+            } => speed.toggle_latched(),
+
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => {
+                if let Some(button) = button_for(keycode) {
+                    let event = InputEvent::Button {
+                        button,
+                        pressed: true,
+                    };
+                    cpu.bus.controllers[0].handle_event(event);
+                    if let Some(keypad) = keypad {
+                        keypad.handle_event(event);
+                        cpu.mem_write(SnakeKeypad::ADDRESS, keypad.read());
+                    }
+                }
+            }
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } => {
+                if let Some(button) = button_for(keycode) {
+                    let event = InputEvent::Button {
+                        button,
+                        pressed: false,
+                    };
+                    cpu.bus.controllers[0].handle_event(event);
+                    if let Some(keypad) = keypad {
+                        keypad.handle_event(event);
+                    }
+                }
+            }
             _ => {}
         }
     }
+    hotkey
+}
+
+fn button_for(keycode: Keycode) -> Option<Button> {
+    match keycode {
+        Keycode::W => Some(Button::Up),
+        Keycode::A => Some(Button::Left),
+        Keycode::S => Some(Button::Down),
+        Keycode::D => Some(Button::Right),
+        Keycode::K => Some(Button::A),
+        Keycode::J => Some(Button::B),
+        Keycode::RShift => Some(Button::Select),
+        Keycode::Return => Some(Button::Start),
+        _ => None,
+    }
 }
 
 fn colour(byte: u8) -> Color {
@@ -121,19 +672,9 @@ fn colour(byte: u8) -> Color {
     }
 }
 
-fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
-    let mut frame_idx = 0;
-    let mut update = false;
-    for i in 0x0200..0x0600 {
-        let colour_idx = cpu.mem_read(i as u16);
-        let (b1, b2, b3) = colour(colour_idx).rgb();
-        if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
-            frame[frame_idx] = b1;
-            frame[frame_idx + 1] = b2;
-            frame[frame_idx + 2] = b3;
-            update = true;
-        }
-        frame_idx += 3;
-    }
-    update
+fn read_screen_state(cpu: &CPU, frame: &mut Frame) {
+    for (idx, addr) in (0x0200..0x0600).enumerate() {
+        let colour_idx = cpu.peek(addr as u16);
+        frame.set_pixel(idx % 32, idx / 32, colour(colour_idx).rgb());
+    }
 }
@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+/// Fast-forward speed control. Holding fast-forward ramps the speed up
+/// smoothly instead of jumping straight to the maximum, and a separate
+/// latched turbo stays on until toggled off.
+#[derive(Debug, Clone)]
+pub struct SpeedControl {
+    /// Speed multiplier reached while fast-forward is held
+    pub max_speed: f32,
+    /// How long it takes to go from normal speed to `max_speed`
+    pub ramp_time: Duration,
+    /// Speed multiplier while the latched turbo is on
+    pub turbo_speed: f32,
+    held: bool,
+    latched: bool,
+    current: f32,
+}
+
+impl Default for SpeedControl {
+    fn default() -> Self {
+        Self {
+            max_speed: 4.0,
+            ramp_time: Duration::from_secs(1),
+            turbo_speed: 2.0,
+            held: false,
+            latched: false,
+            current: 1.0,
+        }
+    }
+}
+
+impl SpeedControl {
+    pub fn set_held(&mut self, held: bool) {
+        self.held = held;
+    }
+
+    pub fn toggle_latched(&mut self) {
+        self.latched = !self.latched;
+    }
+
+    pub fn latched(&self) -> bool {
+        self.latched
+    }
+
+    fn target(&self) -> f32 {
+        if self.held {
+            self.max_speed
+        } else if self.latched {
+            self.turbo_speed
+        } else {
+            1.0
+        }
+    }
+
+    /// Advances the ramp by `elapsed` real time and returns the new speed
+    pub fn update(&mut self, elapsed: Duration) -> f32 {
+        let target = self.target();
+        if self.held && self.current < target {
+            let ramp = elapsed.as_secs_f32() / self.ramp_time.as_secs_f32().max(f32::EPSILON);
+            self.current = (self.current + (self.max_speed - 1.0) * ramp).min(target);
+        } else {
+            // Letting go or toggling turbo takes effect straight away
+            self.current = target;
+        }
+        self.current
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.current
+    }
+
+    /// Audio should be muted while the speed is off 1x, since there is no
+    /// pitch correction
+    pub fn mute_audio(&self) -> bool {
+        self.current != 1.0
+    }
+
+    /// Scales a real-time delay by the current speed
+    pub fn scale(&self, delay: Duration) -> Duration {
+        delay.div_f32(self.current)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ramp_while_held() {
+        let mut speed = SpeedControl::default();
+        assert_eq!(speed.update(Duration::from_millis(100)), 1.0);
+
+        speed.set_held(true);
+        assert_eq!(speed.update(Duration::from_millis(500)), 2.5);
+        assert_eq!(speed.update(Duration::from_millis(500)), 4.0);
+        // Doesn't overshoot
+        assert_eq!(speed.update(Duration::from_millis(500)), 4.0);
+        assert!(speed.mute_audio());
+
+        speed.set_held(false);
+        assert_eq!(speed.update(Duration::from_millis(1)), 1.0);
+        assert!(!speed.mute_audio());
+    }
+
+    #[test]
+    fn test_latched_turbo() {
+        let mut speed = SpeedControl::default();
+        speed.toggle_latched();
+        assert_eq!(speed.update(Duration::ZERO), 2.0);
+        assert_eq!(
+            speed.scale(Duration::from_millis(10)),
+            Duration::from_millis(5)
+        );
+
+        // Holding fast-forward still ramps on top of the turbo speed
+        speed.set_held(true);
+        assert_eq!(speed.update(Duration::from_millis(500)), 3.5);
+
+        speed.set_held(false);
+        speed.toggle_latched();
+        assert_eq!(speed.update(Duration::ZERO), 1.0);
+    }
+}
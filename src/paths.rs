@@ -0,0 +1,212 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+
+/// Creating this file next to the executable turns on portable mode
+pub const PORTABLE_SENTINEL: &str = "portable.txt";
+/// Folder next to the executable that holds everything in portable mode
+const PORTABLE_DIR: &str = "data";
+
+/// The kinds of files the emulator persists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataKind {
+    Config,
+    Saves,
+    States,
+    Screenshots,
+    Logs,
+    /// Per-game cheat lists
+    Cheats,
+}
+
+impl DataKind {
+    pub const ALL: [DataKind; 6] = [
+        DataKind::Config,
+        DataKind::Saves,
+        DataKind::States,
+        DataKind::Screenshots,
+        DataKind::Logs,
+        DataKind::Cheats,
+    ];
+
+    /// Works out which kind a file written by older builds belongs to
+    fn from_legacy_file(path: &Path) -> Option<Self> {
+        if path.file_name()? == "config.toml" {
+            return Some(DataKind::Config);
+        }
+        match path.extension()?.to_str()? {
+            "sav" => Some(DataKind::Saves),
+            "state" => Some(DataKind::States),
+            _ => None,
+        }
+    }
+}
+
+/// Where each kind of data lives on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataDirs {
+    config: PathBuf,
+    saves: PathBuf,
+    states: PathBuf,
+    screenshots: PathBuf,
+    logs: PathBuf,
+    cheats: PathBuf,
+}
+
+impl DataDirs {
+    /// The platform's conventional locations, i.e. the XDG base directories
+    /// on Linux, `~/Library/Application Support` on macOS and `%APPDATA%` on
+    /// Windows. Returns `None` if no home directory can be found.
+    pub fn platform() -> Option<Self> {
+        let dirs = ProjectDirs::from("", "", "nes-emu-rs")?;
+        let data = dirs.data_dir();
+        Some(Self {
+            config: dirs.config_dir().to_path_buf(),
+            saves: data.join("saves"),
+            states: data.join("states"),
+            screenshots: data.join("screenshots"),
+            logs: dirs
+                .state_dir()
+                .unwrap_or_else(|| dirs.data_local_dir())
+                .join("logs"),
+            cheats: data.join("cheats"),
+        })
+    }
+
+    /// Keeps everything in sub directories of `root`
+    pub fn rooted_at(root: impl AsRef<Path>) -> Self {
+        let root = root.as_ref();
+        Self {
+            config: root.join("config"),
+            saves: root.join("saves"),
+            states: root.join("states"),
+            screenshots: root.join("screenshots"),
+            logs: root.join("logs"),
+            cheats: root.join("cheats"),
+        }
+    }
+
+    /// The root to use in portable mode, which is on when `flag` is set (e.g.
+    /// from `--portable`) or a [`PORTABLE_SENTINEL`] file sits next to the
+    /// executable in `exe_dir`
+    pub fn portable_root(exe_dir: &Path, flag: bool) -> Option<PathBuf> {
+        (flag || exe_dir.join(PORTABLE_SENTINEL).is_file()).then(|| exe_dir.join(PORTABLE_DIR))
+    }
+
+    /// Uses `root_override` (e.g. from `--data-dir`) when given, otherwise
+    /// the platform directories, falling back to the current directory
+    pub fn resolve(root_override: Option<&Path>) -> Self {
+        match root_override {
+            Some(root) => Self::rooted_at(root),
+            None => Self::platform().unwrap_or_else(|| Self::rooted_at(".")),
+        }
+    }
+
+    pub fn dir(&self, kind: DataKind) -> &Path {
+        match kind {
+            DataKind::Config => &self.config,
+            DataKind::Saves => &self.saves,
+            DataKind::States => &self.states,
+            DataKind::Screenshots => &self.screenshots,
+            DataKind::Logs => &self.logs,
+            DataKind::Cheats => &self.cheats,
+        }
+    }
+
+    pub fn create_all(&self) -> io::Result<()> {
+        for kind in DataKind::ALL {
+            fs::create_dir_all(self.dir(kind))?;
+        }
+        Ok(())
+    }
+
+    /// Moves config, `.sav` and `.state` files that older builds left in
+    /// `legacy` into their proper directory. Files that already exist at the
+    /// destination are left alone. Returns the new paths of moved files.
+    pub fn migrate_legacy(&self, legacy: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut moved = vec![];
+        for entry in fs::read_dir(legacy)? {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let (Some(kind), Some(name)) = (DataKind::from_legacy_file(&path), path.file_name())
+            else {
+                continue;
+            };
+
+            let target = self.dir(kind).join(name);
+            if target.exists() {
+                continue;
+            }
+            fs::create_dir_all(self.dir(kind))?;
+            // Fall back to copying when renaming across file systems fails
+            if fs::rename(&path, &target).is_err() {
+                fs::copy(&path, &target)?;
+                fs::remove_file(&path)?;
+            }
+            moved.push(target);
+        }
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_override_roots_every_kind() {
+        let dirs = DataDirs::resolve(Some(Path::new("/tmp/nes")));
+        for kind in DataKind::ALL {
+            assert!(dirs.dir(kind).starts_with("/tmp/nes"));
+        }
+        assert_eq!(dirs.dir(DataKind::Saves), Path::new("/tmp/nes/saves"));
+    }
+
+    #[test]
+    fn test_portable_root() {
+        let exe_dir = tempfile::tempdir().unwrap();
+        assert_eq!(DataDirs::portable_root(exe_dir.path(), false), None);
+        assert_eq!(
+            DataDirs::portable_root(exe_dir.path(), true),
+            Some(exe_dir.path().join("data"))
+        );
+
+        fs::write(exe_dir.path().join(PORTABLE_SENTINEL), "").unwrap();
+        assert_eq!(
+            DataDirs::portable_root(exe_dir.path(), false),
+            Some(exe_dir.path().join("data"))
+        );
+    }
+
+    #[test]
+    fn test_migrate_legacy_files() {
+        let legacy = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        fs::write(legacy.path().join("zelda.sav"), [1, 2, 3]).unwrap();
+        fs::write(legacy.path().join("zelda.state"), [4]).unwrap();
+        fs::write(legacy.path().join("config.toml"), "").unwrap();
+        fs::write(legacy.path().join("zelda.nes"), [0]).unwrap();
+
+        let dirs = DataDirs::rooted_at(root.path());
+        let mut moved = dirs.migrate_legacy(legacy.path()).unwrap();
+        moved.sort();
+
+        assert_eq!(
+            moved,
+            [
+                root.path().join("config/config.toml"),
+                root.path().join("saves/zelda.sav"),
+                root.path().join("states/zelda.state"),
+            ]
+        );
+        assert_eq!(fs::read(&moved[1]).unwrap(), [1, 2, 3]);
+        // ROMs aren't ours to move
+        assert!(legacy.path().join("zelda.nes").exists());
+        assert!(!legacy.path().join("zelda.sav").exists());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+use crate::hardware::CPU;
+
+/// How often [`SavFile::autosave`] writes, so a crash loses at most this
+/// much progress
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A cartridge's battery backed RAM, kept next to the ROM as `<rom>.sav`
+/// holding the raw bytes of `$6000..=$7FFF` like other emulators do
+pub struct SavFile {
+    path: PathBuf,
+    /// What's on disk, so unchanged RAM isn't written again
+    saved: Vec<u8>,
+    last_autosave: Instant,
+}
+
+impl SavFile {
+    /// Where the save for the ROM at `rom` lives
+    pub fn path_for(rom: &Path) -> PathBuf {
+        rom.with_extension("sav")
+    }
+
+    /// Loads the save for the ROM at `rom` into the cartridge in `cpu`,
+    /// treating a missing one as a new game. `None` if the cartridge has
+    /// no battery.
+    pub fn open(rom: &Path, cpu: &mut CPU, now: Instant) -> Result<Option<Self>> {
+        let Some(ram) = cpu.bus.battery_ram() else {
+            return Ok(None);
+        };
+        let path = Self::path_for(rom);
+        let saved = if path.exists() {
+            let saved =
+                std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+            cpu.bus
+                .load_battery_ram(&saved)
+                .with_context(|| format!("loading {}", path.display()))?;
+            saved
+        } else {
+            ram
+        };
+        Ok(Some(Self {
+            path,
+            saved,
+            last_autosave: now,
+        }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the RAM if it changed since the last save, e.g. on exit.
+    /// Returns whether anything was written.
+    pub fn save(&mut self, cpu: &CPU) -> Result<bool> {
+        let Some(ram) = cpu.bus.battery_ram() else {
+            return Ok(false);
+        };
+        if ram == self.saved {
+            return Ok(false);
+        }
+        // Renamed over the old save so a crash mid-write can't lose it
+        let temp = self.path.with_extension("sav.tmp");
+        std::fs::write(&temp, &ram).with_context(|| format!("writing {}", temp.display()))?;
+        std::fs::rename(&temp, &self.path)
+            .with_context(|| format!("writing {}", self.path.display()))?;
+        self.saved = ram;
+        Ok(true)
+    }
+
+    /// [`SavFile::save`] at most once every [`AUTOSAVE_INTERVAL`], for
+    /// calling every frame
+    pub fn autosave(&mut self, cpu: &CPU, now: Instant) -> Result<bool> {
+        if now.duration_since(self.last_autosave) < AUTOSAVE_INTERVAL {
+            return Ok(false);
+        }
+        self.last_autosave = now;
+        self.save(cpu)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::Rom;
+
+    use super::*;
+
+    /// An NROM cartridge with 16KB PRG ROM and 8KB CHR ROM
+    fn cpu_with_rom(flags6: u8) -> CPU {
+        let mut raw = vec![b'N', b'E', b'S', 0x1A, 1, 1, flags6, 0];
+        raw.resize(16 + 0x4000 + 0x2000, 0);
+        let mut cpu = CPU::default();
+        cpu.load_rom(&Rom::new(&raw).unwrap()).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let rom = dir.path().join("zelda.nes");
+        let start = Instant::now();
+
+        let mut cpu = cpu_with_rom(0b10);
+        let mut sav = SavFile::open(&rom, &mut cpu, start).unwrap().unwrap();
+        // Nothing to write for a new game that hasn't touched its RAM
+        assert!(!sav.save(&cpu).unwrap());
+        assert!(!dir.path().join("zelda.sav").exists());
+
+        cpu.mem_write(0x6010, 0x42);
+        assert!(!sav.autosave(&cpu, start).unwrap());
+        assert!(sav.autosave(&cpu, start + AUTOSAVE_INTERVAL).unwrap());
+        assert!(!sav.save(&cpu).unwrap());
+
+        let mut cpu = cpu_with_rom(0b10);
+        SavFile::open(&rom, &mut cpu, start).unwrap().unwrap();
+        assert_eq!(cpu.peek(0x6010), 0x42);
+    }
+
+    #[test]
+    fn test_no_battery() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cpu = cpu_with_rom(0);
+        let sav = SavFile::open(&dir.path().join("smb.nes"), &mut cpu, Instant::now()).unwrap();
+        assert!(sav.is_none());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::frame::Frame;
+
+type Pixel = (u8, u8, u8);
+
+/// Software pixel-art scalers applied to a frame before it is uploaded,
+/// for front-ends without a shader path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scaler {
+    #[default]
+    None,
+    Scale2x,
+    Scale3x,
+}
+
+impl Scaler {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Scaler::None),
+            "scale2x" => Some(Scaler::Scale2x),
+            "scale3x" => Some(Scaler::Scale3x),
+            _ => None,
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        match self {
+            Scaler::None => 1,
+            Scaler::Scale2x => 2,
+            Scaler::Scale3x => 3,
+        }
+    }
+
+    /// A frame with the right dimensions to hold the output for `src`
+    pub fn output_frame(&self, src: &Frame) -> Frame {
+        Frame::new(src.width() * self.factor(), src.height() * self.factor())
+    }
+
+    /// Scales `src` into `dst`, which must come from [`Scaler::output_frame`]
+    pub fn apply(&self, src: &Frame, dst: &mut Frame) {
+        assert_eq!(dst.width(), src.width() * self.factor());
+        assert_eq!(dst.height(), src.height() * self.factor());
+
+        for y in 0..src.height() {
+            for x in 0..src.width() {
+                match self {
+                    Scaler::None => dst.set_pixel(x, y, pixel(src, x as isize, y as isize)),
+                    Scaler::Scale2x => scale2x(src, dst, x, y),
+                    Scaler::Scale3x => scale3x(src, dst, x, y),
+                }
+            }
+        }
+    }
+}
+
+/// Reads a pixel, clamping coordinates to the edge of the frame
+#[inline]
+fn pixel(frame: &Frame, x: isize, y: isize) -> Pixel {
+    let x = x.clamp(0, frame.width() as isize - 1) as usize;
+    let y = y.clamp(0, frame.height() as isize - 1) as usize;
+    let base = (y * frame.width() + x) * 3;
+    let data = frame.data();
+    (data[base], data[base + 1], data[base + 2])
+}
+
+/// The 3x3 neighbourhood around a pixel, named as in the scale2x docs:
+///
+/// ```text
+/// A B C
+/// D E F
+/// G H I
+/// ```
+struct Neighbours {
+    a: Pixel,
+    b: Pixel,
+    c: Pixel,
+    d: Pixel,
+    e: Pixel,
+    f: Pixel,
+    g: Pixel,
+    h: Pixel,
+    i: Pixel,
+}
+
+impl Neighbours {
+    #[inline]
+    fn around(frame: &Frame, x: usize, y: usize) -> Self {
+        let (x, y) = (x as isize, y as isize);
+        Self {
+            a: pixel(frame, x - 1, y - 1),
+            b: pixel(frame, x, y - 1),
+            c: pixel(frame, x + 1, y - 1),
+            d: pixel(frame, x - 1, y),
+            e: pixel(frame, x, y),
+            f: pixel(frame, x + 1, y),
+            g: pixel(frame, x - 1, y + 1),
+            h: pixel(frame, x, y + 1),
+            i: pixel(frame, x + 1, y + 1),
+        }
+    }
+}
+
+/// https://www.scale2x.it/algorithm
+fn scale2x(src: &Frame, dst: &mut Frame, x: usize, y: usize) {
+    let Neighbours { b, d, e, f, h, .. } = Neighbours::around(src, x, y);
+
+    let out = if b != h && d != f {
+        [
+            if d == b { d } else { e },
+            if b == f { f } else { e },
+            if d == h { d } else { e },
+            if h == f { f } else { e },
+        ]
+    } else {
+        [e; 4]
+    };
+
+    let (dx, dy) = (x * 2, y * 2);
+    dst.set_pixel(dx, dy, out[0]);
+    dst.set_pixel(dx + 1, dy, out[1]);
+    dst.set_pixel(dx, dy + 1, out[2]);
+    dst.set_pixel(dx + 1, dy + 1, out[3]);
+}
+
+/// https://www.scale2x.it/algorithm
+fn scale3x(src: &Frame, dst: &mut Frame, x: usize, y: usize) {
+    let Neighbours {
+        a,
+        b,
+        c,
+        d,
+        e,
+        f,
+        g,
+        h,
+        i,
+    } = Neighbours::around(src, x, y);
+
+    let out = if b != h && d != f {
+        [
+            if d == b { d } else { e },
+            if (d == b && e != c) || (b == f && e != a) {
+                b
+            } else {
+                e
+            },
+            if b == f { f } else { e },
+            if (d == b && e != g) || (d == h && e != a) {
+                d
+            } else {
+                e
+            },
+            e,
+            if (b == f && e != i) || (h == f && e != c) {
+                f
+            } else {
+                e
+            },
+            if d == h { d } else { e },
+            if (d == h && e != i) || (h == f && e != g) {
+                h
+            } else {
+                e
+            },
+            if h == f { f } else { e },
+        ]
+    } else {
+        [e; 9]
+    };
+
+    let (dx, dy) = (x * 3, y * 3);
+    for (idx, rgb) in out.into_iter().enumerate() {
+        dst.set_pixel(dx + idx % 3, dy + idx / 3, rgb);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const W: Pixel = (255, 255, 255);
+    const K: Pixel = (0, 0, 0);
+
+    fn frame_from(pixels: &[&[Pixel]]) -> Frame {
+        let mut frame = Frame::new(pixels[0].len(), pixels.len());
+        for (y, row) in pixels.iter().enumerate() {
+            for (x, rgb) in row.iter().enumerate() {
+                frame.set_pixel(x, y, *rgb);
+            }
+        }
+        frame
+    }
+
+    fn rows(frame: &Frame) -> Vec<Vec<Pixel>> {
+        (0..frame.height() as isize)
+            .map(|y| {
+                (0..frame.width() as isize)
+                    .map(|x| pixel(frame, x, y))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scale2x_rounds_corner() {
+        let src = frame_from(&[&[W, K], &[K, K]]);
+        let mut dst = Scaler::Scale2x.output_frame(&src);
+        Scaler::Scale2x.apply(&src, &mut dst);
+
+        #[rustfmt::skip]
+        let expected = [
+            [W, W, K, K],
+            [W, K, K, K],
+            [K, K, K, K],
+            [K, K, K, K],
+        ];
+        assert_eq!(rows(&dst), expected);
+    }
+
+    #[test]
+    fn test_flat_colour_is_unchanged() {
+        for scaler in [Scaler::None, Scaler::Scale2x, Scaler::Scale3x] {
+            let src = frame_from(&[&[W, W, W], &[W, W, W]]);
+            let mut dst = scaler.output_frame(&src);
+            scaler.apply(&src, &mut dst);
+
+            assert!(rows(&dst).iter().flatten().all(|rgb| *rgb == W));
+        }
+    }
+
+    #[test]
+    fn test_scale3x_keeps_centre_pixel() {
+        let src = frame_from(&[&[K, K, K], &[K, W, K], &[K, K, K]]);
+        let mut dst = Scaler::Scale3x.output_frame(&src);
+        Scaler::Scale3x.apply(&src, &mut dst);
+
+        let out = rows(&dst);
+        // A lone pixel has no edges to smooth so it becomes a 3x3 block
+        for row in &out[3..6] {
+            assert_eq!(row[3..6], [W, W, W]);
+        }
+        assert_eq!(out[2][3], K);
+    }
+}
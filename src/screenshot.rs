@@ -0,0 +1,95 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+
+use crate::frame::Frame;
+
+/// Which stage of the video pipeline a screenshot captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenshotKind {
+    /// The frame as the emulator produced it, for pixel artists
+    Native,
+    /// The frame after scalers/filters, as shown on screen
+    Output,
+}
+
+impl ScreenshotKind {
+    fn suffix(&self) -> &'static str {
+        match self {
+            ScreenshotKind::Native => "native",
+            ScreenshotKind::Output => "output",
+        }
+    }
+}
+
+/// A fresh file name in `dir` like `snake-1700000000123-native.png`
+pub fn screenshot_path(dir: &Path, name: &str, kind: ScreenshotKind) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    let mut path = dir.join(format!("{name}-{millis}-{}.png", kind.suffix()));
+    let mut copy = 1;
+    while path.exists() {
+        path = dir.join(format!("{name}-{millis}-{}-{copy}.png", kind.suffix()));
+        copy += 1;
+    }
+    path
+}
+
+/// Numbered file name for frame sequence dumps, e.g. `frame-000042.png`,
+/// so they sort in playback order
+pub fn dump_path(dir: &Path, frame: u64) -> PathBuf {
+    dir.join(format!("frame-{frame:06}.png"))
+}
+
+pub fn save_png(frame: &Frame, path: &Path) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        frame.width() as u32,
+        frame.height() as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(frame.data())?;
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_save_png_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut frame = Frame::new(3, 2);
+        frame.set_pixel(2, 1, (10, 20, 30));
+
+        let path = screenshot_path(dir.path(), "test", ScreenshotKind::Native);
+        assert!(path.to_string_lossy().ends_with("-native.png"));
+        save_png(&frame, &path).unwrap();
+
+        let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(&data[..info.buffer_size()], frame.data());
+    }
+
+    #[test]
+    fn test_dump_paths_sort_in_order() {
+        let dir = Path::new("dumps");
+        assert_eq!(dump_path(dir, 42), dir.join("frame-000042.png"));
+        assert!(dump_path(dir, 9) < dump_path(dir, 10));
+    }
+}
@@ -0,0 +1,75 @@
+//! Ensures the per-frame path (CPU loop, frame buffer updates and frame
+//! statistics) doesn't touch the heap once everything is set up.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    time::Duration,
+};
+
+use nes_emu_rs::{
+    frame::Frame,
+    frame_stats::{FrameStats, FrameTiming},
+    hardware::CPU,
+};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Counts the allocations made by the current thread while running `f`
+fn allocations_during(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn test_frame_loop_does_not_allocate() {
+    // Same nested countdown loop as the cpu benchmark, writing X into the
+    // screen area so the frame buffer sees changes
+    let program = [
+        0xa0, 0x10, 0xa2, 0x00, 0xe8, 0x8e, 0x00, 0x02, 0xd0, 0xfa, 0x88, 0xd0, 0xf5, 0x00,
+    ];
+    let mut cpu = CPU::default();
+    cpu.load(&program).unwrap();
+    cpu.reset();
+
+    let mut frame = Frame::new(32, 32);
+    let mut stats = FrameStats::default();
+
+    let allocations = allocations_during(|| {
+        cpu.run_with_callback(|cpu| {
+            for (idx, addr) in (0x0200..0x0600).enumerate() {
+                let value = cpu.mem_read(addr);
+                frame.set_pixel(idx % 32, idx / 32, (value, value, value));
+            }
+            if frame.take_dirty_rows().is_some() {
+                stats.push(FrameTiming {
+                    emulation: Duration::from_micros(1),
+                    ..Default::default()
+                });
+            }
+        })
+        .unwrap();
+    });
+
+    assert!(stats.frames() > 0);
+    assert_eq!(allocations, 0);
+}